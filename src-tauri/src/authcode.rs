@@ -0,0 +1,361 @@
+use std::{collections::HashMap, time::Duration};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::Instant,
+};
+use url::Url;
+
+use crate::{
+    login::{AuthPrompt, LoginFlow, LoginStep, SsoToken, token_from_output},
+    registration::{ClientRegistration, get_or_register_client},
+    trace_err_ret,
+};
+
+const REDIRECT_PATH: &str = "/oauth/callback";
+
+/// How long to wait for the redirect when no login timeout is configured.
+const DEFAULT_REDIRECT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Requests to the listener larger than this can't be the redirect.
+const MAX_REQUEST_SIZE: usize = 16 * 1024;
+
+/// How long a connection gets to send its request. The browser does so right
+/// away, a preconnect or another local process that
+/// sends nothing mustn't hold up the login.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a request to the listener amounted to.
+enum Callback {
+    Code(String),
+    Denied(String),
+    Ignored,
+}
+
+/// An authorization code login with PKCE, ready to send the user off to
+/// approve it. The identity provider redirects back to a listener on an
+/// ephemeral loopback port, so no code has to be confirmed and nothing polls.
+pub(crate) struct AuthCodeLogin {
+    listener: TcpListener,
+    redirect_uri: String,
+    registration: ClientRegistration,
+}
+
+impl AuthCodeLogin {
+    /// Registers a client for the authorization code grant and binds the
+    /// listener. Fails when either isn't possible, so the caller can fall
+    /// back to the device flow before the user has seen anything.
+    pub(crate) async fn prepare(
+        sso_oidc_client: &aws_sdk_ssooidc::Client,
+        sso_region: &str,
+        sso_start_url: &str,
+    ) -> Result<Self, anyhow::Error> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let redirect_uri = format!(
+            "http://127.0.0.1:{}{}",
+            listener.local_addr()?.port(),
+            REDIRECT_PATH
+        );
+        let registration = get_or_register_client(
+            sso_oidc_client,
+            sso_region,
+            sso_start_url,
+            LoginFlow::AuthorizationCode,
+        )
+        .await?;
+        Ok(Self {
+            listener,
+            redirect_uri,
+            registration,
+        })
+    }
+
+    /// Opens the authorization page through `prompt`, waits for the redirect
+    /// and exchanges its code for a token.
+    pub(crate) async fn run(
+        self,
+        sso_oidc_client: &aws_sdk_ssooidc::Client,
+        sso_region: &str,
+        timeout: Option<Duration>,
+        prompt: &mut (dyn AuthPrompt + Send),
+    ) -> Result<(SsoToken, ClientRegistration), anyhow::Error> {
+        prompt.check_cancelled()?;
+        let code_verifier = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+        let state = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>());
+
+        let authorize_url = Url::parse_with_params(
+            &format!("https://oidc.{}.amazonaws.com/authorize", sso_region),
+            [
+                ("response_type", "code"),
+                ("client_id", self.registration.client_id.as_str()),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("state", state.as_str()),
+                ("code_challenge_method", "S256"),
+                ("code_challenge", code_challenge.as_str()),
+                ("scopes", "sso:account:access"),
+            ],
+        )?;
+        prompt.progress(LoginStep::AuthorizationStarted {
+            user_code: None,
+            verification_uri: authorize_url.to_string(),
+        });
+        prompt.open(authorize_url.as_str(), None)?;
+        prompt.progress(LoginStep::WaitingForApproval);
+
+        let code = wait_for_code(
+            &self.listener,
+            &state,
+            timeout.unwrap_or(DEFAULT_REDIRECT_TIMEOUT),
+            prompt,
+        )
+        .await;
+        if let Err(e) = prompt.close() {
+            tracing::warn!("unable to close the login prompt: {:?}", e);
+        }
+
+        let output = sso_oidc_client
+            .create_token()
+            .client_id(&self.registration.client_id)
+            .client_secret(&self.registration.client_secret)
+            .grant_type("authorization_code")
+            .code(code?)
+            .redirect_uri(&self.redirect_uri)
+            .code_verifier(code_verifier)
+            .send()
+            .await?;
+        Ok((token_from_output(&output)?, self.registration))
+    }
+}
+
+/// Accepts connections until the redirect with the code arrives, checking
+/// every second and between connections whether the user abandoned the login.
+/// A connection that fails only costs that connection.
+async fn wait_for_code(
+    listener: &TcpListener,
+    state: &str,
+    timeout: Duration,
+    prompt: &mut (dyn AuthPrompt + Send),
+) -> Result<String, anyhow::Error> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        prompt.check_cancelled()?;
+        if Instant::now() >= deadline {
+            return Err(trace_err_ret(&format!(
+                "Timed out after {} minutes waiting for the login to be approved!",
+                timeout.as_secs().div_ceil(60)
+            )));
+        }
+        let Ok(accepted) = tokio::time::timeout(Duration::from_secs(1), listener.accept()).await
+        else {
+            continue;
+        };
+        let stream = match accepted {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::warn!(
+                    "unable to accept a connection on the login listener: {:?}",
+                    e
+                );
+                continue;
+            }
+        };
+        match handle_request(stream, state).await {
+            Ok(Callback::Code(code)) => return Ok(code),
+            Ok(Callback::Denied(reason)) => {
+                return Err(trace_err_ret(&format!("The login was denied: {}", reason)));
+            }
+            Ok(Callback::Ignored) => {}
+            Err(e) => tracing::warn!("unable to answer a request to the login listener: {:?}", e),
+        }
+    }
+}
+
+/// Answers one request to the listener. Returns the code once the identity
+/// provider redirects with it, anything else, e.g. a favicon request or a
+/// stale tab from an earlier login, is turned away.
+async fn handle_request(mut stream: TcpStream, state: &str) -> Result<Callback, anyhow::Error> {
+    let Some(target) = read_request_target(&mut stream).await? else {
+        respond(&mut stream, "400 Bad Request", "Unexpected request.").await?;
+        return Ok(Callback::Ignored);
+    };
+    let url = Url::parse(&format!("http://127.0.0.1{}", target))?;
+    if url.path() != REDIRECT_PATH {
+        respond(&mut stream, "404 Not Found", "Not found.").await?;
+        return Ok(Callback::Ignored);
+    }
+
+    let params = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+    if params.get("state").map(|s| s.as_str()) != Some(state) {
+        respond(
+            &mut stream,
+            "400 Bad Request",
+            "This login link is out of date, start the login again.",
+        )
+        .await?;
+        return Ok(Callback::Ignored);
+    }
+    if let Some(error) = params.get("error") {
+        let reason = params.get("error_description").unwrap_or(error).clone();
+        if let Err(e) = respond(&mut stream, "200 OK", "The login was not approved.").await {
+            tracing::warn!("unable to answer the login redirect: {:?}", e);
+        }
+        return Ok(Callback::Denied(reason));
+    }
+    let Some(code) = params.get("code") else {
+        respond(
+            &mut stream,
+            "400 Bad Request",
+            "The login redirect came without a code.",
+        )
+        .await?;
+        return Ok(Callback::Ignored);
+    };
+    if let Err(e) = respond(
+        &mut stream,
+        "200 OK",
+        "Signed in to AWS, you can close this window.",
+    )
+    .await
+    {
+        tracing::warn!("unable to answer the login redirect: {:?}", e);
+    }
+    Ok(Callback::Code(code.clone()))
+}
+
+/// Reads the request head and returns the target of a `GET` request. Gives up
+/// on connections that don't send it within `REQUEST_TIMEOUT`, heads cut off
+/// at `MAX_REQUEST_SIZE` or by the connection closing are turned away.
+async fn read_request_target(stream: &mut TcpStream) -> Result<Option<String>, anyhow::Error> {
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    let mut request = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        let read = tokio::time::timeout_at(deadline, stream.read(&mut chunk))
+            .await
+            .map_err(|_| trace_err_ret("Connection to the login listener sent no request!"))??;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&chunk[..read]);
+    }
+    if !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        return Ok(None);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(target)) => Ok(Some(target.to_string())),
+        _ => Ok(None),
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) -> Result<(), anyhow::Error> {
+    let body = format!(
+        "<!doctype html><html><head><title>Awth Butler</title></head>\
+         <body><p>{}</p></body></html>",
+        message
+    );
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATE: &str = "expected-state";
+
+    /// Sends `request` to a listener answered by `handle_request`, returning
+    /// what it made of it and the response the client got.
+    async fn send(request: Vec<u8>) -> (Result<Callback, anyhow::Error>, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            // oversized requests aren't read to the end, so writing may fail
+            let _ = stream.write_all(&request).await;
+            let mut response = String::new();
+            let _ = stream.read_to_string(&mut response).await;
+            response
+        });
+        let (stream, _) = listener.accept().await.unwrap();
+        let callback = handle_request(stream, STATE).await;
+        (callback, client.await.unwrap())
+    }
+
+    fn get(target: &str) -> Vec<u8> {
+        format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", target).into_bytes()
+    }
+
+    #[tokio::test]
+    async fn the_redirect_hands_over_its_code() {
+        let (callback, response) = send(get("/oauth/callback?code=abc&state=expected-state")).await;
+        assert!(matches!(callback, Ok(Callback::Code(code)) if code == "abc"));
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[tokio::test]
+    async fn a_state_mismatch_is_ignored() {
+        let (callback, response) = send(get("/oauth/callback?code=abc&state=stale")).await;
+        assert!(matches!(callback, Ok(Callback::Ignored)));
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request"));
+
+        let (callback, _) = send(get("/oauth/callback?code=abc")).await;
+        assert!(matches!(callback, Ok(Callback::Ignored)));
+    }
+
+    #[tokio::test]
+    async fn an_error_callback_denies_the_login() {
+        let (callback, response) = send(get(
+            "/oauth/callback?error=access_denied&error_description=Not%20allowed&state=expected-state",
+        ))
+        .await;
+        assert!(matches!(callback, Ok(Callback::Denied(reason)) if reason == "Not allowed"));
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[tokio::test]
+    async fn a_redirect_without_a_code_is_ignored() {
+        let (callback, response) = send(get("/oauth/callback?state=expected-state")).await;
+        assert!(matches!(callback, Ok(Callback::Ignored)));
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request"));
+    }
+
+    #[tokio::test]
+    async fn other_paths_and_methods_are_ignored() {
+        let (callback, response) = send(get("/favicon.ico")).await;
+        assert!(matches!(callback, Ok(Callback::Ignored)));
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+
+        let post = b"POST /oauth/callback?code=abc&state=expected-state HTTP/1.1\r\n\r\n".to_vec();
+        let (callback, _) = send(post).await;
+        assert!(matches!(callback, Ok(Callback::Ignored)));
+    }
+
+    #[tokio::test]
+    async fn an_oversized_request_line_is_ignored() {
+        let target = format!(
+            "/oauth/callback?state=expected-state&code={}",
+            "x".repeat(MAX_REQUEST_SIZE)
+        );
+        let (callback, _) = send(get(&target)).await;
+        assert!(matches!(callback, Ok(Callback::Ignored)));
+    }
+}
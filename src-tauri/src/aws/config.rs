@@ -1,10 +1,17 @@
 use std::collections::HashMap;
 
-use crate::trace_err_ret;
+use crate::{aws::paths::config_file, trace_err_ret};
 
+/// Sub-properties nested under a property, e.g. the settings under `s3 =`.
+pub(crate) type NestedProperties = HashMap<String, String>;
+
+#[derive(Clone)]
 pub(crate) struct Profile {
     pub(crate) name: String,
     pub(crate) properties: HashMap<String, String>,
+    pub(crate) nested: HashMap<String, NestedProperties>,
+    /// How many sections of the file were merged into this profile.
+    pub(crate) section_count: usize,
 }
 
 impl Profile {
@@ -17,11 +24,90 @@ impl Profile {
             .get(name.to_ascii_lowercase().as_str())
             .map(|prop| prop.as_str())
     }
+
+    pub(crate) fn nested(&self, name: &str) -> Option<&NestedProperties> {
+        self.nested.get(name.to_ascii_lowercase().as_str())
+    }
+
+    pub(crate) fn sso_session(&self) -> Option<&str> {
+        self.get("sso_session")
+    }
+
+    pub(crate) fn sso_account_id(&self) -> Option<&str> {
+        self.get("sso_account_id")
+    }
+
+    pub(crate) fn sso_role_name(&self) -> Option<&str> {
+        self.get("sso_role_name")
+    }
+
+    /// Only set on legacy profiles, session profiles use their session's.
+    pub(crate) fn sso_region(&self) -> Option<&str> {
+        self.get("sso_region")
+    }
+
+    /// Only set on legacy profiles, session profiles use their session's.
+    pub(crate) fn sso_start_url(&self) -> Option<&str> {
+        self.get("sso_start_url")
+    }
+
+    /// Name of the `[services ...]` section this profile uses.
+    pub(crate) fn services(&self) -> Option<&str> {
+        self.get("services")
+    }
+
+    pub(crate) fn region(&self) -> Option<&str> {
+        self.get("region")
+    }
+
+    pub(crate) fn role_arn(&self) -> Option<&str> {
+        self.get("role_arn")
+    }
+
+    pub(crate) fn source_profile(&self) -> Option<&str> {
+        self.get("source_profile")
+    }
+
+    pub(crate) fn external_id(&self) -> Option<&str> {
+        self.get("external_id")
+    }
+
+    pub(crate) fn role_session_name(&self) -> Option<&str> {
+        self.get("role_session_name")
+    }
+
+    pub(crate) fn duration_seconds(&self) -> Option<&str> {
+        self.get("duration_seconds")
+    }
+
+    /// Butler's own grouping of profiles, a comma separated `butler_tags` list.
+    pub(crate) fn tags(&self) -> impl Iterator<Item = &str> {
+        self.get("butler_tags")
+            .into_iter()
+            .flat_map(|tags| tags.split(','))
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+    }
+
+    /// Whether this profile assumes a role with another profile's credentials.
+    pub(crate) fn is_chained(&self) -> bool {
+        self.role_arn().is_some() && self.source_profile().is_some()
+    }
+
+    /// Whether this profile logs in through SSO without an sso-session.
+    pub(crate) fn is_legacy_sso(&self) -> bool {
+        self.sso_session().is_none()
+            && self.sso_region().is_some()
+            && self.sso_start_url().is_some()
+    }
 }
 
+#[derive(Clone)]
 pub(crate) struct Session {
     pub(crate) name: String,
     pub(crate) properties: HashMap<String, String>,
+    /// How many sections of the file were merged into this session.
+    pub(crate) section_count: usize,
 }
 
 impl Session {
@@ -34,56 +120,436 @@ impl Session {
             .get(name.to_ascii_lowercase().as_str())
             .map(|prop| prop.as_str())
     }
+
+    pub(crate) fn sso_region(&self) -> Option<&str> {
+        self.get("sso_region")
+    }
+
+    pub(crate) fn sso_start_url(&self) -> Option<&str> {
+        self.get("sso_start_url")
+    }
+}
+
+/// A `[services ...]` section, mapping service identifiers to their settings.
+#[derive(Clone)]
+pub(crate) struct Services {
+    pub(crate) name: String,
+    pub(crate) services: HashMap<String, NestedProperties>,
+}
+
+impl Services {
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn service(&self, service_id: &str) -> Option<&NestedProperties> {
+        self.services.get(service_id.to_ascii_lowercase().as_str())
+    }
 }
 
+#[derive(Clone)]
 pub(crate) struct AwsConfigSections {
     pub(crate) profiles: HashMap<String, Profile>,
     pub(crate) sessions: HashMap<String, Session>,
+    pub(crate) services: HashMap<String, Services>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SectionKind {
+    /// `[default]`, which loses to an explicit `[profile default]`
+    BareDefault,
+    Profile,
+    Session,
+    Services,
+}
+
+/// One section as it appears in the file, before duplicates are merged.
+struct RawSection {
+    kind: SectionKind,
+    name: String,
+    properties: HashMap<String, String>,
+    nested: HashMap<String, NestedProperties>,
+}
+
+fn parse_header(header: &str) -> Option<(SectionKind, String)> {
+    if header == "default" {
+        return Some((SectionKind::BareDefault, header.to_string()));
+    }
+    let (prefix, name) = header.split_once(char::is_whitespace)?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return None;
+    }
+    let kind = match prefix {
+        "profile" => SectionKind::Profile,
+        "sso-session" => SectionKind::Session,
+        "services" => SectionKind::Services,
+        _ => return None,
+    };
+    Some((kind, name.to_string()))
+}
+
+/// Values end at a `#` or `;` that follows whitespace.
+fn strip_inline_comment(value: &str) -> &str {
+    let mut prev_whitespace = false;
+    for (idx, c) in value.char_indices() {
+        if prev_whitespace && (c == '#' || c == ';') {
+            return value[..idx].trim_end();
+        }
+        prev_whitespace = c.is_whitespace();
+    }
+    value
+}
+
+fn parse_sections(contents: &str) -> Vec<RawSection> {
+    let mut sections: Vec<RawSection> = Vec::new();
+    // properties only count while inside a recognised section
+    let mut in_section = false;
+    let mut last_key: Option<String> = None;
+
+    for line in contents.lines() {
+        let line = line.trim_end();
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with(['#', ';']) {
+            continue;
+        }
+        let indented = trimmed.len() != line.len();
+
+        if !indented && trimmed.starts_with('[') {
+            last_key = None;
+            let header = trimmed[1..].split(']').next().unwrap_or_default().trim();
+            let parsed = if trimmed.contains(']') {
+                parse_header(header)
+            } else {
+                None
+            };
+            match parsed {
+                Some((kind, name)) => {
+                    sections.push(RawSection {
+                        kind,
+                        name,
+                        properties: HashMap::new(),
+                        nested: HashMap::new(),
+                    });
+                    in_section = true;
+                }
+                None => {
+                    tracing::warn!("ignoring unrecognised config section [{}]", header);
+                    in_section = false;
+                }
+            }
+            continue;
+        }
+
+        let Some(section) = sections.last_mut().filter(|_| in_section) else {
+            continue;
+        };
+
+        if indented {
+            // indented lines either nest under an empty property or continue
+            // the value of the previous one
+            let Some(key) = &last_key else {
+                continue;
+            };
+            let parent_empty = section.properties.get(key).is_some_and(|v| v.is_empty());
+            match trimmed.split_once('=') {
+                Some((sub_key, sub_value)) if parent_empty || section.nested.contains_key(key) => {
+                    section.properties.remove(key);
+                    section.nested.entry(key.clone()).or_default().insert(
+                        sub_key.trim().to_ascii_lowercase(),
+                        strip_inline_comment(sub_value.trim()).to_string(),
+                    );
+                }
+                _ => {
+                    if let Some(value) = section.properties.get_mut(key) {
+                        value.push('\n');
+                        value.push_str(trimmed);
+                    }
+                }
+            }
+            continue;
+        }
+
+        let Some((key, value)) = trimmed.split_once('=') else {
+            tracing::warn!("ignoring malformed config line in [{}]", section.name);
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        // a repeated key replaces everything set before it
+        section.nested.remove(&key);
+        section
+            .properties
+            .insert(key.clone(), strip_inline_comment(value.trim()).to_string());
+        last_key = Some(key);
+    }
+    sections
+}
+
+fn merge_into(
+    properties: &mut HashMap<String, String>,
+    nested: &mut HashMap<String, NestedProperties>,
+    section: RawSection,
+) {
+    for (key, value) in section.properties {
+        nested.remove(&key);
+        properties.insert(key, value);
+    }
+    for (key, sub_properties) in section.nested {
+        properties.remove(&key);
+        nested.entry(key).or_default().extend(sub_properties);
+    }
 }
 
 impl AwsConfigSections {
-    pub(crate) fn parse() -> Result<Self, anyhow::Error> {
-        let mut profiles = HashMap::new();
-        let mut sessions = HashMap::new();
+    /// Builds the sections following the shared config precedence rules:
+    /// duplicate sections are merged with later properties winning, and an
+    /// explicit `[profile default]` replaces a bare `[default]`.
+    pub(crate) fn parse_str(contents: &str) -> Self {
+        let mut profiles: HashMap<String, Profile> = HashMap::new();
+        let mut sessions: HashMap<String, Session> = HashMap::new();
+        let mut services: HashMap<String, Services> = HashMap::new();
 
-        let home_dir =
-            dirs::home_dir().ok_or_else(|| trace_err_ret("No home directory detected!"))?;
-        let config_path = home_dir.join(".aws").join("config");
+        let sections = parse_sections(contents);
+        let explicit_default = sections
+            .iter()
+            .any(|s| s.kind == SectionKind::Profile && s.name == "default");
+
+        for section in sections {
+            match section.kind {
+                SectionKind::BareDefault if explicit_default => {
+                    tracing::warn!("ignoring [default] in favor of [profile default]");
+                }
+                SectionKind::BareDefault | SectionKind::Profile => {
+                    let profile = profiles
+                        .entry(section.name.clone())
+                        .or_insert_with(|| Profile {
+                            name: section.name.clone(),
+                            properties: HashMap::new(),
+                            nested: HashMap::new(),
+                            section_count: 0,
+                        });
+                    profile.section_count += 1;
+                    merge_into(&mut profile.properties, &mut profile.nested, section);
+                }
+                SectionKind::Session => {
+                    let session = sessions
+                        .entry(section.name.clone())
+                        .or_insert_with(|| Session {
+                            name: section.name.clone(),
+                            properties: HashMap::new(),
+                            section_count: 0,
+                        });
+                    session.section_count += 1;
+                    let mut ignored = HashMap::new();
+                    merge_into(&mut session.properties, &mut ignored, section);
+                }
+                SectionKind::Services => {
+                    let entry = services
+                        .entry(section.name.clone())
+                        .or_insert_with(|| Services {
+                            name: section.name.clone(),
+                            services: HashMap::new(),
+                        });
+                    let mut ignored = HashMap::new();
+                    merge_into(&mut ignored, &mut entry.services, section);
+                }
+            }
+        }
+
+        AwsConfigSections {
+            profiles,
+            sessions,
+            services,
+        }
+    }
+
+    pub(crate) fn parse() -> Result<Self, anyhow::Error> {
+        let config_path = config_file()?;
         if !config_path.exists() {
             return Err(trace_err_ret(&format!(
                 "No config file found at {:?}. Please configure accordingly!",
                 config_path
             )));
-        } else {
-            let config_ini = ini::Ini::load_from_file(&config_path)?;
-            for (section_name, section) in config_ini.iter() {
-                let mut properties = HashMap::new();
-                for (key, value) in section.iter() {
-                    properties.insert(key.to_string(), value.to_string());
-                }
-                if let Some(sec_name) = section_name {
-                    if sec_name.starts_with("profile ") {
-                        let profile_name = sec_name.trim_start_matches("profile ");
-                        profiles.insert(
-                            profile_name.to_string(),
-                            Profile {
-                                name: profile_name.to_string(),
-                                properties,
-                            },
-                        );
-                    } else if sec_name.starts_with("sso-session ") {
-                        let session_name = sec_name.trim_start_matches("sso-session ");
-                        sessions.insert(
-                            session_name.to_string(),
-                            Session {
-                                name: session_name.to_string(),
-                                properties,
-                            },
-                        );
-                    }
-                }
+        }
+        Ok(Self::parse_str(&std::fs::read_to_string(&config_path)?))
+    }
+
+    /// Resolves the `sso_region` and `sso_start_url` a profile logs in with,
+    /// from its sso-session or, for legacy profiles, from the profile itself.
+    pub(crate) fn sso_settings<'a>(&'a self, profile: &'a Profile) -> Option<(&'a str, &'a str)> {
+        match profile.sso_session() {
+            Some(session_name) => {
+                let session = self.sessions.get(session_name)?;
+                Some((session.sso_region()?, session.sso_start_url()?))
+            }
+            None => Some((profile.sso_region()?, profile.sso_start_url()?)),
+        }
+    }
+
+    /// The SSO profile at the bottom of a role chain, found by following
+    /// `source_profile` until a profile that logs in through SSO. Broken and
+    /// circular chains have no root.
+    pub(crate) fn chain_root<'a>(&'a self, profile: &'a Profile) -> Option<&'a Profile> {
+        let mut current = profile;
+        for _ in 0..=self.profiles.len() {
+            if !current.is_chained() {
+                return self.sso_settings(current).map(|_| current);
+            }
+            current = self.profiles.get(current.source_profile()?)?;
+        }
+        None
+    }
+
+    /// The chained profiles between the chain root and `profile`, sources
+    /// first and ending with `profile` itself. Empty for profiles that aren't
+    /// chained, `None` for broken and circular chains.
+    pub(crate) fn chain_path<'a>(&'a self, profile: &'a Profile) -> Option<Vec<&'a Profile>> {
+        let mut path = Vec::new();
+        let mut current = profile;
+        for _ in 0..=self.profiles.len() {
+            if !current.is_chained() {
+                path.reverse();
+                return Some(path);
+            }
+            path.push(current);
+            current = self.profiles.get(current.source_profile()?)?;
+        }
+        None
+    }
+
+    /// Every profile chained on top of `source_names`, directly or through
+    /// other chained profiles, ordered so sources come before the profiles
+    /// assuming a role with their credentials.
+    pub(crate) fn chained_profiles(&self, source_names: &[&str]) -> Vec<&Profile> {
+        let mut resolved = source_names.to_vec();
+        let mut chained = Vec::new();
+        loop {
+            let mut next = self
+                .profiles
+                .values()
+                .filter(|prof| prof.is_chained() && !resolved.contains(&prof.name()))
+                .filter(|prof| {
+                    prof.source_profile()
+                        .is_some_and(|source| resolved.contains(&source))
+                })
+                .collect::<Vec<_>>();
+            if next.is_empty() {
+                return chained;
             }
+            next.sort_by(|a, b| a.name().cmp(b.name()));
+            resolved.extend(next.iter().map(|prof| prof.name()));
+            chained.extend(next);
         }
-        Ok(AwsConfigSections { profiles, sessions })
+    }
+
+    /// The `[services ...]` section a profile refers to, if it exists.
+    pub(crate) fn services_for(&self, profile: &Profile) -> Option<&Services> {
+        self.services.get(profile.services()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_default_is_the_default_profile() {
+        let parsed = AwsConfigSections::parse_str("[default]\nregion = eu-west-1\n");
+        assert_eq!(parsed.profiles["default"].region(), Some("eu-west-1"));
+    }
+
+    #[test]
+    fn explicit_default_profile_wins_over_bare_default() {
+        let parsed = AwsConfigSections::parse_str(
+            "[profile default]\nregion = us-east-1\n\n[default]\nregion = eu-west-1\noutput = json\n",
+        );
+        let default = &parsed.profiles["default"];
+        assert_eq!(default.region(), Some("us-east-1"));
+        assert_eq!(default.get("output"), None);
+    }
+
+    #[test]
+    fn duplicate_sections_merge_with_later_values_winning() {
+        let parsed = AwsConfigSections::parse_str(
+            "[profile dev]\nregion = eu-west-1\noutput = json\n\n\
+             [sso-session my-sso]\nsso_region = eu-west-1\n\n\
+             [profile dev]\nregion = us-east-1\n\n\
+             [sso-session my-sso]\nsso_start_url = https://my-org.awsapps.com/start\n",
+        );
+        let dev = &parsed.profiles["dev"];
+        assert_eq!(dev.region(), Some("us-east-1"));
+        assert_eq!(dev.get("output"), Some("json"));
+        assert_eq!(dev.section_count, 2);
+        let session = &parsed.sessions["my-sso"];
+        assert_eq!(session.section_count, 2);
+        assert_eq!(session.sso_region(), Some("eu-west-1"));
+        assert_eq!(
+            session.sso_start_url(),
+            Some("https://my-org.awsapps.com/start")
+        );
+    }
+
+    #[test]
+    fn services_sections_map_services_to_their_settings() {
+        let parsed = AwsConfigSections::parse_str(
+            "[profile dev]\nservices = local\n\n\
+             [services local]\n\
+             s3 =\n  endpoint_url = http://localhost:9000\n\
+             DynamoDB =\n  endpoint_url = http://localhost:8000\n  region = local\n",
+        );
+        let services = parsed.services_for(&parsed.profiles["dev"]).unwrap();
+        assert_eq!(services.name(), "local");
+        assert_eq!(
+            services.service("s3").unwrap()["endpoint_url"],
+            "http://localhost:9000"
+        );
+        let dynamodb = services.service("dynamodb").unwrap();
+        assert_eq!(dynamodb["endpoint_url"], "http://localhost:8000");
+        assert_eq!(dynamodb["region"], "local");
+    }
+
+    #[test]
+    fn nested_blocks_continuations_and_comments() {
+        let parsed = AwsConfigSections::parse_str(
+            "# comment\n[profile  dev] ; trailing\n\
+             Region = eu-west-1 # inline comment\n\
+             s3 =\n  max_concurrent_requests = 20\n\
+             note = first\n  second\n\
+             url = https://example.com/#anchor\n",
+        );
+        let dev = &parsed.profiles["dev"];
+        assert_eq!(dev.region(), Some("eu-west-1"));
+        assert_eq!(dev.nested("s3").unwrap()["max_concurrent_requests"], "20");
+        assert_eq!(dev.get("s3"), None);
+        assert_eq!(dev.get("note"), Some("first\nsecond"));
+        assert_eq!(dev.get("url"), Some("https://example.com/#anchor"));
+    }
+
+    #[test]
+    fn unknown_sections_and_their_properties_are_ignored() {
+        let parsed = AwsConfigSections::parse_str(
+            "[plugins]\nregion = eu-west-1\n\n[profile two words]\nregion = eu-west-1\n",
+        );
+        assert!(parsed.profiles.is_empty());
+    }
+
+    #[test]
+    fn chain_path_only_follows_the_requested_chain() {
+        let parsed = AwsConfigSections::parse_str(
+            "[profile sso]\nsso_region = eu-west-1\nsso_start_url = https://my-org.awsapps.com/start\n\n\
+             [profile admin]\nrole_arn = arn:aws:iam::111111111111:role/Admin\nsource_profile = sso\n\n\
+             [profile deploy]\nrole_arn = arn:aws:iam::222222222222:role/Deploy\nsource_profile = admin\n\n\
+             [profile audit]\nrole_arn = arn:aws:iam::333333333333:role/Audit\nsource_profile = sso\n\n\
+             [profile loop]\nrole_arn = arn:aws:iam::444444444444:role/Loop\nsource_profile = loop\n",
+        );
+        let names = |profile: &str| {
+            parsed
+                .chain_path(&parsed.profiles[profile])
+                .map(|path| path.iter().map(|prof| prof.name()).collect::<Vec<_>>())
+        };
+        assert_eq!(names("deploy"), Some(vec!["admin", "deploy"]));
+        assert_eq!(names("audit"), Some(vec!["audit"]));
+        assert_eq!(names("sso"), Some(vec![]));
+        assert_eq!(names("loop"), None);
     }
 }
@@ -1,6 +1,13 @@
 use aws_sdk_sso::operation::get_role_credentials::GetRoleCredentialsOutput;
 
-use crate::{trace_err_ret, utils::parse_aws_date_robust};
+use crate::{
+    aws::{
+        ini_edit::{edit_file_locked, remove_sections, rename_section, upsert_section},
+        paths::credentials_file,
+    },
+    trace_err_ret,
+    utils::parse_aws_date_robust,
+};
 
 pub(crate) struct ButlerRoleCreds {
     pub(crate) expiration: chrono::DateTime<chrono::Utc>,
@@ -10,8 +17,7 @@ pub(crate) fn get_credentials_for_profile(
     profile_name: &str,
 ) -> Result<Option<ButlerRoleCreds>, anyhow::Error> {
     // Get the credentials file path
-    let home_dir = dirs::home_dir().ok_or_else(|| trace_err_ret("No home directory detected!"))?;
-    let credentials_path = home_dir.join(".aws").join("credentials");
+    let credentials_path = credentials_file()?;
 
     // Ensure file exists, if not return None
     if !credentials_path.exists() {
@@ -38,61 +44,126 @@ pub(crate) fn get_credentials_for_profile(
     }
 }
 
+/// Role credentials as written to the credentials file, whether they came
+/// from SSO or from assuming a role through STS.
+pub(crate) struct RoleCredentials {
+    pub(crate) access_key_id: String,
+    pub(crate) secret_access_key: String,
+    pub(crate) session_token: String,
+    pub(crate) expiration: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<GetRoleCredentialsOutput> for RoleCredentials {
+    type Error = anyhow::Error;
+
+    fn try_from(output: GetRoleCredentialsOutput) -> Result<Self, Self::Error> {
+        let role_creds = output
+            .role_credentials
+            .ok_or_else(|| trace_err_ret("Missing role credentials after login!"))?;
+        Ok(RoleCredentials {
+            access_key_id: role_creds
+                .access_key_id
+                .ok_or_else(|| trace_err_ret("Missing access key ID!"))?,
+            secret_access_key: role_creds
+                .secret_access_key
+                .ok_or_else(|| trace_err_ret("Missing secret access key!"))?,
+            session_token: role_creds
+                .session_token
+                .ok_or_else(|| trace_err_ret("Missing session token!"))?,
+            // SSO reports the expiration in milliseconds
+            expiration: chrono::DateTime::from_timestamp_millis(role_creds.expiration)
+                .ok_or_else(|| trace_err_ret("Invalid expiration timestamp!"))?,
+        })
+    }
+}
+
+impl TryFrom<&aws_sdk_sts::types::Credentials> for RoleCredentials {
+    type Error = anyhow::Error;
+
+    fn try_from(creds: &aws_sdk_sts::types::Credentials) -> Result<Self, Self::Error> {
+        let expiration = creds.expiration();
+        Ok(RoleCredentials {
+            access_key_id: creds.access_key_id().to_string(),
+            secret_access_key: creds.secret_access_key().to_string(),
+            session_token: creds.session_token().to_string(),
+            expiration: chrono::DateTime::from_timestamp(
+                expiration.secs(),
+                expiration.subsec_nanos(),
+            )
+            .ok_or_else(|| trace_err_ret("Invalid expiration timestamp!"))?,
+        })
+    }
+}
+
+/// Profiles with long-lived keys in the credentials file, told apart from the
+/// temporary credentials butler writes by their missing session token.
+pub(crate) fn profiles_with_static_keys() -> Result<Vec<String>, anyhow::Error> {
+    let credentials_path = credentials_file()?;
+    if !credentials_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let ini = ini::Ini::load_from_file(&credentials_path)?;
+    Ok(ini
+        .iter()
+        .filter_map(|(name, section)| {
+            (section.contains_key("aws_access_key_id")
+                && !section.contains_key("aws_session_token"))
+            .then_some(name?.to_string())
+        })
+        .collect())
+}
+
 pub(crate) fn store_credentials_for_profile(
     profile_name: &str,
-    creds: &GetRoleCredentialsOutput,
+    creds: &RoleCredentials,
 ) -> Result<(), anyhow::Error> {
     // Get the credentials file path
-    let home_dir = dirs::home_dir().ok_or_else(|| trace_err_ret("No home directory detected!"))?;
-    let credentials_path = home_dir.join(".aws").join("credentials");
+    let credentials_path = credentials_file()?;
 
-    // Ensure the directory exists
-    if let Some(parent) = credentials_path.parent() {
-        std::fs::create_dir_all(parent)?;
-    }
+    // Format expiration as ISO 8601
+    let expiration = creds.expiration.to_rfc3339();
+    let properties = [
+        ("aws_access_key_id", creds.access_key_id.as_str()),
+        ("aws_secret_access_key", creds.secret_access_key.as_str()),
+        ("aws_session_token", creds.session_token.as_str()),
+        ("aws_security_token", creds.session_token.as_str()),
+        ("aws_session_expiration", expiration.as_str()),
+    ];
 
-    // Parse the existing credentials file or create a new one
-    let mut ini = if credentials_path.exists() {
-        ini::Ini::load_from_file(&credentials_path)?
-    } else {
-        ini::Ini::new()
-    };
+    // Only this profile's section is rewritten, the rest of the file is kept
+    // as is, including comments
+    edit_file_locked(&credentials_path, |contents| {
+        Ok(Some(upsert_section(contents, profile_name, &properties)))
+    })
+}
 
-    // Update credentials for this profile
-    let role_creds = creds
-        .role_credentials
-        .as_ref()
-        .ok_or_else(|| trace_err_ret("Missing role credentials after login!"))?;
+/// Strips the sections of the given profiles from the credentials file.
+pub(crate) fn remove_credentials_for_profiles(profile_names: &[&str]) -> Result<(), anyhow::Error> {
+    // Get the credentials file path
+    let credentials_path = credentials_file()?;
 
-    // Format expiration as ISO 8601
-    let expiration = chrono::DateTime::<chrono::Utc>::from_timestamp_millis(
-        role_creds.expiration, // Convert milliseconds to seconds
-    )
-    .ok_or_else(|| trace_err_ret("Invalid expiration timestamp!"))?
-    .to_rfc3339();
-
-    let session_token = role_creds
-        .session_token()
-        .ok_or_else(|| trace_err_ret("Missing session token!"))?;
-    ini.with_section(Some(profile_name.to_string()))
-        .set(
-            "aws_access_key_id",
-            role_creds
-                .access_key_id()
-                .ok_or_else(|| trace_err_ret("Missing access key ID!"))?,
-        )
-        .set(
-            "aws_secret_access_key",
-            role_creds
-                .secret_access_key()
-                .ok_or_else(|| trace_err_ret("Missing secret access key!"))?,
-        )
-        .set("aws_session_token", session_token)
-        .set("aws_security_token", session_token)
-        .set("aws_session_expiration", expiration);
+    // Nothing to strip if there is no credentials file
+    if !credentials_path.exists() {
+        return Ok(());
+    }
 
-    // Write back to the file
-    ini.write_to_file(&credentials_path)?;
+    // Avoid rewriting the file when nothing changed
+    edit_file_locked(&credentials_path, |contents| {
+        Ok(remove_sections(contents, profile_names))
+    })
+}
 
-    Ok(())
+/// Moves the credentials of a renamed profile to its new section.
+pub(crate) fn rename_credentials_for_profile(
+    old_name: &str,
+    new_name: &str,
+) -> Result<(), anyhow::Error> {
+    let credentials_path = credentials_file()?;
+    if !credentials_path.exists() {
+        return Ok(());
+    }
+    edit_file_locked(&credentials_path, |contents| {
+        Ok(rename_section(contents, old_name, new_name))
+    })
 }
@@ -0,0 +1,495 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use crate::trace_err_ret;
+
+/// A `[section]` of an INI file, kept as the raw lines it was read from.
+struct RawSection<'a> {
+    name: String,
+    lines: Vec<&'a str>,
+}
+
+/// Splits a file into the lines before the first header and its sections.
+/// Every line keeps its line ending so untouched parts can be written back
+/// byte for byte.
+fn split_sections(contents: &str) -> (Vec<&str>, Vec<RawSection<'_>>) {
+    let mut preamble = Vec::new();
+    let mut sections: Vec<RawSection> = Vec::new();
+    for line in contents.split_inclusive('\n') {
+        if let Some(name) = section_header(line) {
+            sections.push(RawSection {
+                name,
+                lines: vec![line],
+            });
+        } else if let Some(section) = sections.last_mut() {
+            section.lines.push(line);
+        } else {
+            preamble.push(line);
+        }
+    }
+    (preamble, sections)
+}
+
+/// The name in a header line, with the whitespace between its words collapsed
+/// like the config parser does, so `[profile  dev]` is found as `profile dev`.
+fn section_header(line: &str) -> Option<String> {
+    let rest = line.trim().strip_prefix('[')?;
+    let end = rest.find(']')?;
+    Some(rest[..end].split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+fn line_key(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    // indented lines are continuations of nested values, not keys
+    if trimmed.len() != line.len() || trimmed.starts_with(['#', ';']) {
+        return None;
+    }
+    trimmed.split_once('=').map(|(key, _)| key.trim())
+}
+
+/// Whether a line is indented under the key before it, a continuation of its
+/// value or part of its nested block.
+fn is_continuation(line: &str) -> bool {
+    !line.trim().is_empty() && line.starts_with(char::is_whitespace)
+}
+
+/// A `key = value` line without its line ending. The lines of a multi-line
+/// value are indented, so they read back as its continuation.
+fn property_line(key: &str, value: &str, eol: &str) -> String {
+    let lines = value
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    format!("{} = {}", key, lines.join(&format!("{}    ", eol)))
+}
+
+fn line_ending(contents: &str) -> &'static str {
+    if contents.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Sets `properties` in the first section called `name`, appending the section
+/// when it does not exist. Existing keys are updated in place, replacing the
+/// lines indented under them, so comments and other keys of the section
+/// survive, and every other section is untouched.
+pub(crate) fn upsert_section(contents: &str, name: &str, properties: &[(&str, &str)]) -> String {
+    let eol = line_ending(contents);
+    let (preamble, sections) = split_sections(contents);
+    let mut out = preamble.concat();
+    let mut found = false;
+
+    for section in &sections {
+        if found || section.name != name {
+            out.extend(section.lines.iter().copied());
+            continue;
+        }
+        found = true;
+
+        let mut written = vec![false; properties.len()];
+        let mut lines = Vec::with_capacity(section.lines.len() + properties.len());
+        let mut in_replaced_key = false;
+        for line in &section.lines {
+            if in_replaced_key && is_continuation(line) {
+                continue;
+            }
+            let idx = line_key(line).and_then(|key| {
+                properties
+                    .iter()
+                    .position(|(prop, _)| prop.eq_ignore_ascii_case(key))
+            });
+            in_replaced_key = idx.is_some();
+            match idx {
+                Some(idx) if !written[idx] => {
+                    let (key, value) = properties[idx];
+                    let ending = if line.ends_with('\n') { eol } else { "" };
+                    lines.push(format!("{}{}", property_line(key, value, eol), ending));
+                    written[idx] = true;
+                }
+                // drop duplicates of a key that was already written
+                Some(_) => {}
+                None => lines.push(line.to_string()),
+            }
+        }
+
+        // new keys go after the last non-blank line of the section
+        let insert_at = lines
+            .iter()
+            .rposition(|line| !line.trim().is_empty())
+            .map_or(lines.len(), |idx| idx + 1);
+        if let Some(last) = insert_at.checked_sub(1).and_then(|idx| lines.get_mut(idx))
+            && !last.ends_with('\n')
+        {
+            last.push_str(eol);
+        }
+        let new_lines = properties
+            .iter()
+            .zip(&written)
+            .filter(|(_, written)| !**written)
+            .map(|((key, value), _)| format!("{}{}", property_line(key, value, eol), eol))
+            .collect::<Vec<_>>();
+        lines.splice(insert_at..insert_at, new_lines);
+        out.extend(lines);
+    }
+
+    if !found {
+        if !out.is_empty() {
+            if !out.ends_with('\n') {
+                out.push_str(eol);
+            }
+            out.push_str(eol);
+        }
+        out.push_str(&format!("[{}]{}", name, eol));
+        for (key, value) in properties {
+            out.push_str(&property_line(key, value, eol));
+            out.push_str(eol);
+        }
+    }
+    out
+}
+
+/// Removes every section called one of `names`, returning `None` when there
+/// was nothing to remove.
+pub(crate) fn remove_sections(contents: &str, names: &[&str]) -> Option<String> {
+    let (preamble, sections) = split_sections(contents);
+    let mut out = preamble.concat();
+    let mut removed = false;
+    for section in &sections {
+        if names.contains(&section.name.as_str()) {
+            removed = true;
+        } else {
+            out.extend(section.lines.iter().copied());
+        }
+    }
+    removed.then_some(out)
+}
+
+/// Whether the file has a section called `name`.
+pub(crate) fn has_section(contents: &str, name: &str) -> bool {
+    split_sections(contents)
+        .1
+        .iter()
+        .any(|section| section.name == name)
+}
+
+/// Renames every section called `old_name`, keeping its contents as is.
+/// Returns `None` when there is no such section.
+pub(crate) fn rename_section(contents: &str, old_name: &str, new_name: &str) -> Option<String> {
+    let eol = line_ending(contents);
+    let (preamble, sections) = split_sections(contents);
+    let mut out = preamble.concat();
+    let mut renamed = false;
+    for section in &sections {
+        let mut lines = section.lines.iter();
+        if section.name == old_name
+            && let Some(header) = lines.next()
+        {
+            let ending = if header.ends_with('\n') { eol } else { "" };
+            out.push_str(&format!("[{}]{}", new_name, ending));
+            renamed = true;
+        }
+        out.extend(lines.copied());
+    }
+    renamed.then_some(out)
+}
+
+/// Removes `keys` from the first section called `name`, along with the
+/// indented lines nested under them. Returns `None` when nothing was removed.
+pub(crate) fn remove_keys(contents: &str, name: &str, keys: &[&str]) -> Option<String> {
+    let (preamble, sections) = split_sections(contents);
+    let mut out = preamble.concat();
+    let mut found = false;
+    let mut removed = false;
+    for section in &sections {
+        if found || section.name != name {
+            out.extend(section.lines.iter().copied());
+            continue;
+        }
+        found = true;
+
+        let mut in_removed_key = false;
+        for line in &section.lines {
+            match line_key(line) {
+                Some(key) => {
+                    in_removed_key = keys.iter().any(|k| k.eq_ignore_ascii_case(key));
+                }
+                // comments and blank lines end a nested block
+                None if line.trim().is_empty() || !line.starts_with(char::is_whitespace) => {
+                    in_removed_key = false;
+                }
+                None => {}
+            }
+            if in_removed_key {
+                removed = true;
+            } else {
+                out.push_str(line);
+            }
+        }
+    }
+    removed.then_some(out)
+}
+
+/// Writes through a temporary file in the same directory that is renamed over
+/// the original, so readers only ever see the old or the new contents.
+pub(crate) fn write_atomically(path: &Path, contents: &str) -> Result<(), anyhow::Error> {
+    write_through_temp_file(path, contents, false)
+}
+
+/// Like `write_atomically`, but the file ends up only readable by the user
+/// whatever its permissions were before, for files holding secrets.
+pub(crate) fn write_private(path: &Path, contents: &str) -> Result<(), anyhow::Error> {
+    write_through_temp_file(path, contents, true)
+}
+
+/// The temporary file is only readable by the user from the start, as the
+/// contents can be credentials. Unless `private`, the original's permissions
+/// are carried over before the rename. A symlinked file, e.g. from a dotfile
+/// repository, is replaced where the link points so the link keeps working.
+fn write_through_temp_file(
+    path: &Path,
+    contents: &str,
+    private: bool,
+) -> Result<(), anyhow::Error> {
+    let path = &fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = path
+        .parent()
+        .ok_or_else(|| trace_err_ret("File has no parent directory!"))?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| trace_err_ret("Invalid file name!"))?
+        .to_string_lossy();
+    let tmp_path = dir.join(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let result = (|| {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // a leftover temporary file keeps its mode when opened again
+            if tmp_path.exists() {
+                fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        let mut tmp = options.open(&tmp_path)?;
+        tmp.write_all(contents.as_bytes())?;
+        tmp.sync_all()?;
+        if !private && let Ok(meta) = fs::metadata(path) {
+            fs::set_permissions(&tmp_path, meta.permissions())?;
+        }
+        fs::rename(&tmp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    Ok(result?)
+}
+
+/// Rewrites a file under an advisory lock shared by every butler process.
+/// `edit` gets the current contents, empty if the file doesn't exist yet, and
+/// returns the new contents or `None` to leave the file alone.
+pub(crate) fn edit_file_locked(
+    path: &Path,
+    edit: impl FnOnce(&str) -> Result<Option<String>, anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| trace_err_ret("Invalid file name!"))?
+        .to_string_lossy();
+    // lock a sibling file, the real one is replaced on every write
+    let lock_file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_file_name(format!(".{}.lock", file_name)))?;
+    lock_file.lock()?;
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(new_contents) = edit(&contents)? {
+        write_atomically(path, &new_contents)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+# managed by hand
+[default]
+region = eu-west-1
+
+[profile dev] # the dev account
+; keep this comment
+sso_session = my-sso
+sso_role_name = Admin
+s3 =
+  max_concurrent_requests = 20
+
+[sso-session my-sso]
+sso_start_url = https://my-org.awsapps.com/start
+sso_region = eu-west-1
+";
+
+    #[test]
+    fn upsert_updates_keys_in_place() {
+        let out = upsert_section(CONFIG, "profile dev", &[("SSO_ROLE_NAME", "ReadOnly")]);
+        assert_eq!(
+            out,
+            CONFIG.replace("sso_role_name = Admin", "SSO_ROLE_NAME = ReadOnly")
+        );
+    }
+
+    #[test]
+    fn upsert_adds_keys_after_the_last_line_of_the_section() {
+        let out = upsert_section(CONFIG, "default", &[("output", "json")]);
+        assert_eq!(
+            out,
+            CONFIG.replace(
+                "region = eu-west-1\n\n",
+                "region = eu-west-1\noutput = json\n\n"
+            )
+        );
+    }
+
+    #[test]
+    fn upsert_replaces_nested_blocks() {
+        let out = upsert_section(CONFIG, "profile dev", &[("s3", "off")]);
+        assert_eq!(
+            out,
+            CONFIG.replace("s3 =\n  max_concurrent_requests = 20\n", "s3 = off\n")
+        );
+    }
+
+    #[test]
+    fn upsert_indents_multi_line_values() {
+        let out = upsert_section("[default]\n", "default", &[("note", "first\nsecond")]);
+        assert_eq!(out, "[default]\nnote = first\n    second\n");
+    }
+
+    #[test]
+    fn upsert_appends_missing_sections() {
+        let out = upsert_section(CONFIG, "profile prod", &[("region", "us-east-1")]);
+        assert_eq!(
+            out,
+            format!("{}\n[profile prod]\nregion = us-east-1\n", CONFIG)
+        );
+    }
+
+    #[test]
+    fn upsert_handles_a_missing_trailing_newline() {
+        let out = upsert_section(
+            "[default]\nregion = eu-west-1",
+            "default",
+            &[("output", "json")],
+        );
+        assert_eq!(out, "[default]\nregion = eu-west-1\noutput = json\n");
+
+        let out = upsert_section(
+            "[default]\nregion = eu-west-1",
+            "profile dev",
+            &[("a", "b")],
+        );
+        assert_eq!(
+            out,
+            "[default]\nregion = eu-west-1\n\n[profile dev]\na = b\n"
+        );
+    }
+
+    #[test]
+    fn upsert_keeps_crlf_line_endings() {
+        let contents = CONFIG.replace('\n', "\r\n");
+        let out = upsert_section(
+            &contents,
+            "default",
+            &[("region", "us-east-1"), ("output", "json")],
+        );
+        assert_eq!(
+            out,
+            contents.replace(
+                "region = eu-west-1\r\n\r\n",
+                "region = us-east-1\r\noutput = json\r\n\r\n"
+            )
+        );
+    }
+
+    #[test]
+    fn headers_match_with_extra_whitespace() {
+        let contents = "[ profile   dev ]\nregion = eu-west-1\n";
+        assert!(has_section(contents, "profile dev"));
+        let out = upsert_section(contents, "profile dev", &[("region", "us-east-1")]);
+        assert_eq!(out, "[ profile   dev ]\nregion = us-east-1\n");
+    }
+
+    #[test]
+    fn remove_sections_keeps_everything_else() {
+        let out = remove_sections(CONFIG, &["profile dev", "profile missing"]).unwrap();
+        let (before, rest) = CONFIG.split_once("[profile dev]").unwrap();
+        let (_, after) = rest.split_once("[sso-session my-sso]").unwrap();
+        assert_eq!(out, format!("{}[sso-session my-sso]{}", before, after));
+        assert_eq!(remove_sections(CONFIG, &["profile missing"]), None);
+    }
+
+    #[test]
+    fn rename_section_keeps_its_contents() {
+        let out = rename_section(CONFIG, "profile dev", "profile staging").unwrap();
+        assert_eq!(
+            out,
+            CONFIG.replace("[profile dev] # the dev account", "[profile staging]")
+        );
+
+        let contents = "[default]\r\nregion = eu-west-1\r\n";
+        let out = rename_section(contents, "default", "profile default").unwrap();
+        assert_eq!(out, "[profile default]\r\nregion = eu-west-1\r\n");
+
+        assert_eq!(rename_section(CONFIG, "profile missing", "profile x"), None);
+    }
+
+    #[test]
+    fn remove_keys_takes_nested_blocks_along() {
+        let out = remove_keys(CONFIG, "profile dev", &["S3", "sso_role_name"]).unwrap();
+        assert_eq!(
+            out,
+            CONFIG
+                .replace("sso_role_name = Admin\n", "")
+                .replace("s3 =\n  max_concurrent_requests = 20\n", "")
+        );
+        assert_eq!(remove_keys(CONFIG, "profile dev", &["output"]), None);
+        assert_eq!(remove_keys(CONFIG, "profile missing", &["region"]), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_replace_the_target_of_a_symlink() {
+        let dir = std::env::temp_dir().join(format!("awth-butler-ini-edit-{}", std::process::id()));
+        fs::create_dir_all(dir.join("dotfiles")).unwrap();
+        let target = dir.join("dotfiles").join("config");
+        let link = dir.join("config");
+        fs::write(&target, "[default]\n").unwrap();
+        let _ = fs::remove_file(&link);
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomically(&link, "[default]\nregion = eu-west-1\n").unwrap();
+        let link_target = fs::read_link(&link);
+        let contents = fs::read_to_string(&target);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(link_target.unwrap(), target);
+        assert_eq!(contents.unwrap(), "[default]\nregion = eu-west-1\n");
+    }
+}
@@ -1,2 +1,4 @@
 pub(crate) mod config;
 pub(crate) mod credentials;
+pub(crate) mod ini_edit;
+pub(crate) mod paths;
@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+
+use crate::trace_err_ret;
+
+/// Standard AWS variable overriding the shared config file location.
+const CONFIG_FILE_ENV: &str = "AWS_CONFIG_FILE";
+/// Standard AWS variable overriding the shared credentials file location.
+const CREDENTIALS_FILE_ENV: &str = "AWS_SHARED_CREDENTIALS_FILE";
+/// AWS has no standard variable for the SSO token cache, so this one is ours.
+const SSO_CACHE_DIR_ENV: &str = "AWTH_BUTLER_SSO_CACHE_DIR";
+
+fn aws_dir() -> Result<PathBuf, anyhow::Error> {
+    Ok(dirs::home_dir()
+        .ok_or_else(|| trace_err_ret("No home directory detected!"))?
+        .join(".aws"))
+}
+
+/// Expands a leading `~` the same way the AWS CLI does for these variables.
+fn expand_home(path: &str) -> Result<PathBuf, anyhow::Error> {
+    match path.strip_prefix("~") {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => Ok(dirs::home_dir()
+            .ok_or_else(|| trace_err_ret("No home directory detected!"))?
+            .join(rest.trim_start_matches(['/', '\\']))),
+        _ => Ok(PathBuf::from(path)),
+    }
+}
+
+fn from_env_or(
+    var: &str,
+    default: impl FnOnce() -> Result<PathBuf, anyhow::Error>,
+) -> Result<PathBuf, anyhow::Error> {
+    match std::env::var(var) {
+        Ok(path) if !path.trim().is_empty() => expand_home(path.trim()),
+        _ => default(),
+    }
+}
+
+/// The shared config file, `~/.aws/config` unless `AWS_CONFIG_FILE` is set.
+pub(crate) fn config_file() -> Result<PathBuf, anyhow::Error> {
+    from_env_or(CONFIG_FILE_ENV, || Ok(aws_dir()?.join("config")))
+}
+
+/// The shared credentials file, `~/.aws/credentials` unless
+/// `AWS_SHARED_CREDENTIALS_FILE` is set.
+pub(crate) fn credentials_file() -> Result<PathBuf, anyhow::Error> {
+    from_env_or(CREDENTIALS_FILE_ENV, || Ok(aws_dir()?.join("credentials")))
+}
+
+/// The SSO token cache, `~/.aws/sso/cache` unless `AWTH_BUTLER_SSO_CACHE_DIR`
+/// is set.
+pub(crate) fn sso_cache_dir() -> Result<PathBuf, anyhow::Error> {
+    from_env_or(SSO_CACHE_DIR_ENV, || {
+        Ok(aws_dir()?.join("sso").join("cache"))
+    })
+}
+
+/// Butler's own cache for `credential_process` credentials.
+pub(crate) fn butler_cache_dir() -> Result<PathBuf, anyhow::Error> {
+    Ok(aws_dir()?.join("butler").join("cache"))
+}
+
+/// An absolute path with symlinks resolved, so paths given relative or
+/// through a symlinked directory compare equal. Only the parent directory is
+/// resolved, files that were just removed or replaced have to match too.
+fn resolved_path(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let resolved = match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => parent.canonicalize().ok().map(|parent| parent.join(name)),
+        _ => None,
+    };
+    resolved.unwrap_or(absolute)
+}
+
+/// A directory resolved like `resolved_path`, including the directory itself
+/// when it is a symlink, as watcher events come with the resolved path.
+fn resolved_dir(dir: &Path) -> PathBuf {
+    dir.canonicalize().unwrap_or_else(|_| resolved_path(dir))
+}
+
+/// Whether a changed path is one of the files butler shows state from.
+pub(crate) fn is_watched_path(path: &Path) -> bool {
+    let path = resolved_path(path);
+    let is_file = |resolved: Result<PathBuf, anyhow::Error>| {
+        resolved.is_ok_and(|r| path == resolved_path(&r))
+    };
+    is_file(config_file())
+        || is_file(credentials_file())
+        || sso_cache_dir().is_ok_and(|dir| path.parent() == Some(resolved_dir(&dir).as_path()))
+}
+
+/// The existing directories to watch so changes to the resolved files and
+/// the SSO cache are noticed, including atomic replacements of the files.
+pub(crate) fn watched_dirs() -> Result<Vec<PathBuf>, anyhow::Error> {
+    Ok(existing_dirs(
+        &[config_file()?, credentials_file()?],
+        &sso_cache_dir()?,
+    ))
+}
+
+/// The resolved directories of `files` and `cache_dir` that exist, once each.
+/// Files given by a bare name live in the current directory.
+fn existing_dirs(files: &[PathBuf], cache_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for dir in files
+        .iter()
+        .filter_map(|file| resolved_path(file).parent().map(resolved_dir))
+        .chain([resolved_dir(cache_dir)])
+    {
+        if dir.is_dir() && !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_file_names_watch_the_current_directory() {
+        let current_dir = std::env::current_dir().unwrap().canonicalize().unwrap();
+        let cache_dir = std::env::temp_dir();
+        let dirs = existing_dirs(
+            &[PathBuf::from("config"), PathBuf::from("./credentials")],
+            &cache_dir,
+        );
+        assert_eq!(dirs, vec![current_dir, cache_dir.canonicalize().unwrap()]);
+    }
+
+    #[test]
+    fn missing_directories_are_left_out() {
+        let missing = std::env::temp_dir().join("awth-butler-missing-dir");
+        let dirs = existing_dirs(&[missing.join("config")], &missing.join("cache"));
+        assert!(dirs.is_empty());
+    }
+}
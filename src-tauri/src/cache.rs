@@ -1,29 +1,50 @@
-use aws_sdk_ssooidc::operation::register_client::RegisterClientOutput;
 use serde_json::json;
 use sha1::{Digest, Sha1};
 use walkdir::WalkDir;
 
-use crate::{handlers::SsoToken, trace_err_ret};
+use crate::{
+    aws::{ini_edit::write_private, paths::sso_cache_dir},
+    login::SsoToken,
+    registration::ClientRegistration,
+    trace_err_ret,
+};
 use std::fs;
 
+/// A cached token along with the registration needed to refresh it.
+pub(crate) struct CachedSsoLogin {
+    pub(crate) token: SsoToken,
+    pub(crate) registration: Option<ClientRegistration>,
+}
+
+impl CachedSsoLogin {
+    /// Whether this entry holds everything needed for a `refresh_token` grant.
+    pub(crate) fn is_refreshable(&self) -> bool {
+        self.token.refresh_token.is_some()
+            && self
+                .registration
+                .as_ref()
+                .is_some_and(|reg| !reg.is_expired())
+    }
+}
+
+pub(crate) fn cache_key_hash(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
 pub(crate) fn store_token_in_cache(
     session_name: Option<&str>,
     sso_start_url: &str,
     sso_region: &str,
-    reg_cli: &RegisterClientOutput,
+    registration: &ClientRegistration,
     token: &SsoToken,
 ) -> Result<(), anyhow::Error> {
     // Calculate hash for the cache file name
-    let mut hasher = Sha1::new();
-    hasher.update(sso_start_url.as_bytes());
-    let hash = format!("{:x}", hasher.finalize());
+    let hash = cache_key_hash(sso_start_url);
 
     // Determine cache directory
-    let cache_dir = dirs::home_dir()
-        .ok_or_else(|| trace_err_ret("home directory not found..."))?
-        .join(".aws")
-        .join("sso")
-        .join("cache");
+    let cache_dir = sso_cache_dir()?;
 
     // Create directory if it doesn't exist
     fs::create_dir_all(&cache_dir)?;
@@ -36,12 +57,10 @@ pub(crate) fn store_token_in_cache(
         "startUrl": sso_start_url,
         "region": sso_region,
         "accessToken": token.access_token,
-        "clientId": reg_cli.client_id,
-        "clientSecret": reg_cli.client_secret,
+        "clientId": registration.client_id,
+        "clientSecret": registration.client_secret,
         "registrationExpiresAt": format!("{}",
-            chrono::DateTime::<chrono::Utc>::from_timestamp(reg_cli.client_secret_expires_at(), 0)
-                .ok_or_else(|| trace_err_ret("Invalid timestamp!"))?
-                .format("%Y-%m-%dT%H:%M:%SZ")),
+            registration.expires_at.format("%Y-%m-%dT%H:%M:%SZ")),
         "expiresAt": format!("{}",
             chrono::DateTime::<chrono::Utc>::from(token.expiration)
                 .format("%Y-%m-%dT%H:%M:%SZ")),
@@ -49,27 +68,64 @@ pub(crate) fn store_token_in_cache(
         "sessionName": session_name,
     });
 
-    // Write to file
-    fs::write(cache_file, serde_json::to_string_pretty(&cache_entry)?)?;
+    // Write to file, only readable by the user as it holds the refresh token
+    let contents = serde_json::to_string_pretty(&cache_entry)?;
+    write_private(&cache_file, &contents)?;
 
     if let Some(sn) = session_name {
-        let mut name_haser = Sha1::new();
-        name_haser.update(sn.as_bytes());
-        let name_hash = format!("{:x}", name_haser.finalize());
+        let name_hash = cache_key_hash(sn);
         let name_cache_file = cache_dir.join(format!("{}.json", name_hash));
 
-        fs::write(name_cache_file, serde_json::to_string_pretty(&cache_entry)?)?;
+        write_private(&name_cache_file, &contents)?;
     }
     Ok(())
 }
 
+/// Removes the cache entries written by `store_token_in_cache`, both the start
+/// URL hash and, for sessions, the session name hash.
+pub(crate) fn remove_token_from_cache(
+    session_name: Option<&str>,
+    sso_start_url: &str,
+) -> Result<(), anyhow::Error> {
+    let cache_dir = sso_cache_dir()?;
+    for key in session_name.into_iter().chain([sso_start_url]) {
+        let cache_file = cache_dir.join(format!("{}.json", cache_key_hash(key)));
+        if cache_file.exists() {
+            fs::remove_file(cache_file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves the cache entry of a renamed sso-session to its new name, so the
+/// session stays logged in.
+pub(crate) fn rename_session_in_cache(
+    old_name: &str,
+    new_name: &str,
+    sso_start_url: &str,
+) -> Result<(), anyhow::Error> {
+    let cache_dir = sso_cache_dir()?;
+    let old_file = cache_dir.join(format!("{}.json", cache_key_hash(old_name)));
+    if !old_file.exists() {
+        return Ok(());
+    }
+
+    let mut json = serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&old_file)?)?;
+    json["sessionName"] = json!(new_name);
+    let contents = serde_json::to_string_pretty(&json)?;
+    for key in [new_name, sso_start_url] {
+        write_private(
+            &cache_dir.join(format!("{}.json", cache_key_hash(key))),
+            &contents,
+        )?;
+    }
+    fs::remove_file(old_file)?;
+    Ok(())
+}
+
 pub(crate) fn get_token_from_cache(session_name: &str) -> Result<Option<SsoToken>, anyhow::Error> {
     // Get the cache directory
-    let cache_dir = dirs::home_dir()
-        .ok_or_else(|| trace_err_ret("home directory not found..."))?
-        .join(".aws")
-        .join("sso")
-        .join("cache");
+    let cache_dir = sso_cache_dir()?;
 
     // Ensure the directory exists
     if !cache_dir.exists() {
@@ -88,42 +144,94 @@ pub(crate) fn get_token_from_cache(session_name: &str) -> Result<Option<SsoToken
             continue;
         }
 
-        // Try to read and parse the file
-        if let Ok(content) = fs::read_to_string(path) {
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&content) {
-                // Check if this is the right session
-                if json.get("sessionName").and_then(|v| v.as_str()) == Some(session_name) {
-                    // Parse the token
-                    let access_token = json
-                        .get("accessToken")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| trace_err_ret("Invalid cache entry!"))?
-                        .to_string();
-
-                    let refresh_token = json
-                        .get("refreshToken")
-                        .and_then(|v| v.as_str())
-                        .map(|s| s.to_string());
-
-                    let expires_at = json
-                        .get("expiresAt")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| trace_err_ret("Invalid cache entry!"))?;
-
-                    // Parse expiration date
-                    let expiration = chrono::DateTime::parse_from_rfc3339(expires_at)?
-                        .with_timezone(&chrono::Utc)
-                        .into();
-
-                    return Ok(Some(SsoToken {
-                        access_token,
-                        refresh_token,
-                        expiration,
-                    }));
-                }
-            }
+        // Try to read and parse the file, checking if this is the right session
+        if let Ok(content) = fs::read_to_string(path)
+            && let Ok(json) = serde_json::from_str::<serde_json::Value>(&content)
+            && json.get("sessionName").and_then(|v| v.as_str()) == Some(session_name)
+        {
+            // Parse the token
+            let access_token = json
+                .get("accessToken")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| trace_err_ret("Invalid cache entry!"))?
+                .to_string();
+
+            let refresh_token = json
+                .get("refreshToken")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+
+            let expires_at = json
+                .get("expiresAt")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| trace_err_ret("Invalid cache entry!"))?;
+
+            // Parse expiration date
+            let expiration = chrono::DateTime::parse_from_rfc3339(expires_at)?
+                .with_timezone(&chrono::Utc)
+                .into();
+
+            return Ok(Some(SsoToken {
+                access_token,
+                refresh_token,
+                expiration,
+            }));
         }
     }
 
     Ok(None)
 }
+
+/// Reads back the full cache entry written by `store_token_in_cache`.
+///
+/// Session logins are looked up by the session name hash, legacy profile
+/// logins by the start URL hash.
+pub(crate) fn get_login_from_cache(
+    session_name: Option<&str>,
+    sso_start_url: &str,
+) -> Result<Option<CachedSsoLogin>, anyhow::Error> {
+    let cache_file = sso_cache_dir()?.join(format!(
+        "{}.json",
+        cache_key_hash(session_name.unwrap_or(sso_start_url))
+    ));
+    if !cache_file.exists() {
+        return Ok(None);
+    }
+
+    let json = serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&cache_file)?)?;
+    let field = |name: &str| json.get(name).and_then(|v| v.as_str());
+
+    let access_token = field("accessToken")
+        .ok_or_else(|| trace_err_ret("Invalid cache entry!"))?
+        .to_string();
+    let expiration = chrono::DateTime::parse_from_rfc3339(
+        field("expiresAt").ok_or_else(|| trace_err_ret("Invalid cache entry!"))?,
+    )?
+    .with_timezone(&chrono::Utc)
+    .into();
+
+    // older or foreign entries may not carry a registration, which just means
+    // they can't be refreshed
+    let registration = match (
+        field("clientId"),
+        field("clientSecret"),
+        field("registrationExpiresAt"),
+    ) {
+        (Some(client_id), Some(client_secret), Some(expires_at)) => Some(ClientRegistration {
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            expires_at: chrono::DateTime::parse_from_rfc3339(expires_at)?
+                .with_timezone(&chrono::Utc),
+        }),
+        _ => None,
+    };
+
+    Ok(Some(CachedSsoLogin {
+        token: SsoToken {
+            access_token,
+            refresh_token: field("refreshToken").map(|s| s.to_string()),
+            expiration,
+        },
+        registration,
+    }))
+}
@@ -0,0 +1,7 @@
+//! Headless front end sharing the login core with the desktop app, for
+//! terminals and remote machines without a display.
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    awth_butler_lib::cli().await
+}
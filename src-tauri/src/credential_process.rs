@@ -0,0 +1,20 @@
+//! Implements the AWS `credential_process` protocol on top of Butler's SSO
+//! cache. Configure it on a profile with:
+//!
+//! ```ini
+//! [profile my-profile-process]
+//! credential_process = awth-butler-credential-process --profile my-profile
+//! ```
+
+const USAGE: &str = "usage: awth-butler-credential-process [--profile] <profile>";
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let profile_name = match args.as_slice() {
+        [flag, name] if flag == "--profile" => name,
+        [name] if !name.starts_with('-') => name,
+        _ => anyhow::bail!(USAGE),
+    };
+    awth_butler_lib::credential_process(profile_name).await
+}
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aws::{
+        config::{AwsConfigSections, Profile},
+        credentials::profiles_with_static_keys,
+    },
+    editor::SectionType,
+    trace_err_ret,
+};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Severity {
+    /// Logins using this section fail.
+    Error,
+    /// Works, but likely not as intended.
+    Warning,
+    /// Could be set up better.
+    Info,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub(crate) enum ConfigFile {
+    Config,
+    Credentials,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub(crate) struct Location {
+    pub(crate) file: ConfigFile,
+    pub(crate) section_type: SectionType,
+    pub(crate) name: String,
+    pub(crate) key: Option<String>,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let file = match self.file {
+            ConfigFile::Config => "config",
+            ConfigFile::Credentials => "credentials",
+        };
+        let header = match (self.file, self.section_type) {
+            (ConfigFile::Config, SectionType::SsoSession) => format!("sso-session {}", self.name),
+            (ConfigFile::Config, SectionType::Profile) => format!("profile {}", self.name),
+            (ConfigFile::Credentials, _) => self.name.clone(),
+        };
+        match &self.key {
+            Some(key) => write!(f, "{} [{}] {}", file, header, key),
+            None => write!(f, "{} [{}]", file, header),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub(crate) struct Finding {
+    pub(crate) severity: Severity,
+    pub(crate) location: Location,
+    pub(crate) message: String,
+    pub(crate) hint: String,
+}
+
+fn config_location(section_type: SectionType, name: &str, key: Option<&str>) -> Location {
+    Location {
+        file: ConfigFile::Config,
+        section_type,
+        name: name.to_string(),
+        key: key.map(|k| k.to_string()),
+    }
+}
+
+/// Repeated sections are merged when read, but the editor would only change
+/// the first one.
+fn repeated_section(section_type: SectionType, name: &str, section_count: usize) -> Finding {
+    let header = match section_type {
+        SectionType::SsoSession => format!("sso-session {}", name),
+        SectionType::Profile => format!("profile {}", name),
+    };
+    Finding {
+        severity: Severity::Warning,
+        location: config_location(section_type, name, None),
+        message: format!("[{}] appears {} times in the config", header, section_count),
+        hint: "Merge the sections into one, later ones override earlier keys and it can't be \
+               edited in the app until then"
+            .to_string(),
+    }
+}
+
+fn check_sessions(profile_set: &AwsConfigSections, findings: &mut Vec<Finding>) {
+    for session in profile_set.sessions.values() {
+        if session.section_count > 1 {
+            findings.push(repeated_section(
+                SectionType::SsoSession,
+                session.name(),
+                session.section_count,
+            ));
+        }
+        for (key, value) in [
+            ("sso_region", session.sso_region()),
+            ("sso_start_url", session.sso_start_url()),
+        ] {
+            if value.is_none() {
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    location: config_location(SectionType::SsoSession, session.name(), None),
+                    message: format!("sso-session {} has no {}", session.name(), key),
+                    hint: format!("Add {} to the sso-session", key),
+                });
+            }
+        }
+    }
+}
+
+fn check_profile(profile_set: &AwsConfigSections, prof: &Profile, findings: &mut Vec<Finding>) {
+    let location = |key: Option<&str>| config_location(SectionType::Profile, prof.name(), key);
+
+    if prof.section_count > 1 {
+        findings.push(repeated_section(
+            SectionType::Profile,
+            prof.name(),
+            prof.section_count,
+        ));
+    }
+
+    if let Some(session_name) = prof.sso_session()
+        && !profile_set.sessions.contains_key(session_name)
+    {
+        findings.push(Finding {
+            severity: Severity::Error,
+            location: location(Some("sso_session")),
+            message: format!(
+                "profile {} uses sso-session {}, which does not exist",
+                prof.name(),
+                session_name
+            ),
+            hint: format!(
+                "Add an [sso-session {}] section or point sso_session at an existing one",
+                session_name
+            ),
+        });
+    }
+
+    if prof.sso_session().is_some() || prof.is_legacy_sso() {
+        for (key, value) in [
+            ("sso_account_id", prof.sso_account_id()),
+            ("sso_role_name", prof.sso_role_name()),
+        ] {
+            if value.is_none() {
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    location: location(None),
+                    message: format!("SSO profile {} has no {}", prof.name(), key),
+                    hint: format!("Add {} to the profile", key),
+                });
+            }
+        }
+    }
+
+    if prof.is_chained() && profile_set.chain_root(prof).is_none() {
+        findings.push(Finding {
+            severity: Severity::Error,
+            location: location(Some("source_profile")),
+            message: format!(
+                "the role chain of profile {} doesn't end at an SSO profile",
+                prof.name()
+            ),
+            hint: "Make sure every source_profile exists, the chain has no loops and \
+                   starts at an SSO profile"
+                .to_string(),
+        });
+    }
+
+    if let Some(services) = prof.services()
+        && profile_set.services_for(prof).is_none()
+    {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            location: location(Some("services")),
+            message: format!(
+                "profile {} uses services section {}, which does not exist",
+                prof.name(),
+                services
+            ),
+            hint: format!("Add a [services {}] section or remove the key", services),
+        });
+    }
+
+    if prof.is_legacy_sso() {
+        let session = profile_set
+            .sessions
+            .values()
+            .filter(|s| s.sso_start_url() == prof.sso_start_url())
+            .min_by(|a, b| a.name().cmp(b.name()));
+        findings.push(Finding {
+            severity: Severity::Info,
+            location: location(Some("sso_start_url")),
+            message: format!("profile {} uses the legacy SSO configuration", prof.name()),
+            hint: match session {
+                Some(session) => format!(
+                    "Replace sso_start_url and sso_region with sso_session = {}",
+                    session.name()
+                ),
+                None => "Move sso_start_url and sso_region into an [sso-session] section \
+                         to get token refresh and a shared login"
+                    .to_string(),
+            },
+        });
+    }
+
+    if prof.get("aws_access_key_id").is_some()
+        && (prof.sso_session().is_some() || prof.is_legacy_sso())
+    {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            location: location(Some("aws_access_key_id")),
+            message: format!(
+                "SSO profile {} also has static keys in the config file",
+                prof.name()
+            ),
+            hint: "Remove aws_access_key_id and aws_secret_access_key from the profile".to_string(),
+        });
+    }
+}
+
+/// Profiles resolving to the same start URL, account and role are flagged,
+/// as they only differ in name.
+fn check_duplicates(profile_set: &AwsConfigSections, findings: &mut Vec<Finding>) {
+    let mut by_role: HashMap<(&str, &str, &str), Vec<&str>> = HashMap::new();
+    for prof in profile_set.profiles.values() {
+        if let (Some((_, sso_start_url)), Some(account_id), Some(role_name)) = (
+            profile_set.sso_settings(prof),
+            prof.sso_account_id(),
+            prof.sso_role_name(),
+        ) {
+            by_role
+                .entry((sso_start_url, account_id, role_name))
+                .or_default()
+                .push(prof.name());
+        }
+    }
+
+    for ((_, account_id, role_name), mut names) in by_role {
+        if names.len() < 2 {
+            continue;
+        }
+        names.sort();
+        for name in &names[1..] {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                location: config_location(SectionType::Profile, name, None),
+                message: format!(
+                    "profile {} uses the same account {} and role {} as {}",
+                    name, account_id, role_name, names[0]
+                ),
+                hint: format!("Use profile {} instead and delete this one", names[0]),
+            });
+        }
+    }
+}
+
+/// Static keys in the credentials file take precedence over SSO for a profile
+/// of the same name in most tools, so the SSO login never gets used.
+fn check_static_keys(profile_set: &AwsConfigSections, findings: &mut Vec<Finding>) {
+    let static_profiles = match profiles_with_static_keys() {
+        Ok(static_profiles) => static_profiles,
+        Err(e) => {
+            tracing::warn!("unable to read the credentials file: {:?}", e);
+            return;
+        }
+    };
+    for name in static_profiles {
+        let Some(prof) = profile_set.profiles.get(&name) else {
+            continue;
+        };
+        if prof.sso_session().is_none() && !prof.is_legacy_sso() {
+            continue;
+        }
+        findings.push(Finding {
+            severity: Severity::Warning,
+            location: Location {
+                file: ConfigFile::Credentials,
+                section_type: SectionType::Profile,
+                name: name.clone(),
+                key: Some("aws_access_key_id".to_string()),
+            },
+            message: format!(
+                "SSO profile {} has long-lived keys in the credentials file",
+                name
+            ),
+            hint: format!(
+                "Remove the [{}] section from the credentials file, butler writes it on login",
+                name
+            ),
+        });
+    }
+}
+
+/// Checks the whole configuration, most severe findings first.
+pub(crate) fn diagnose(profile_set: &AwsConfigSections) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    check_sessions(profile_set, &mut findings);
+    for prof in profile_set.profiles.values() {
+        check_profile(profile_set, prof, &mut findings);
+    }
+    check_duplicates(profile_set, &mut findings);
+    check_static_keys(profile_set, &mut findings);
+
+    findings.sort_by(|a, b| {
+        a.severity
+            .cmp(&b.severity)
+            .then_with(|| a.location.name.cmp(&b.location.name))
+    });
+    findings
+}
+
+/// Fails before a login starts when the sections it depends on have errors,
+/// with the hints to fix them rather than an error from halfway through.
+pub(crate) fn ensure_loginable(
+    profile_set: &AwsConfigSections,
+    section_type: SectionType,
+    name: &str,
+) -> Result<(), anyhow::Error> {
+    let mut involved = vec![(section_type, name)];
+    if section_type == SectionType::Profile
+        && let Some(prof) = profile_set.profiles.get(name)
+    {
+        let root = profile_set.chain_root(prof).unwrap_or(prof);
+        involved.push((SectionType::Profile, root.name()));
+        if let Some(session_name) = root.sso_session() {
+            involved.push((SectionType::SsoSession, session_name));
+        }
+    }
+
+    let problems = diagnose(profile_set)
+        .into_iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .filter(|finding| {
+            involved.iter().any(|(section_type, name)| {
+                finding.location.section_type == *section_type && finding.location.name == *name
+            })
+        })
+        .map(|finding| format!("{} ({})", finding.message, finding.hint))
+        .collect::<Vec<_>>();
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(trace_err_ret(&problems.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION: &str = "[sso-session my-sso]\nsso_region = eu-west-1\nsso_start_url = https://my-org.awsapps.com/start\n\n\
+                           [profile dev]\nsso_session = my-sso\nsso_account_id = 111111111111\nsso_role_name = Admin\n\n";
+
+    /// Every check apart from the credentials file, which isn't in the config.
+    fn config_findings(config: &str) -> Vec<(Severity, String, Option<String>)> {
+        let profile_set = AwsConfigSections::parse_str(config);
+        let mut findings = Vec::new();
+        check_sessions(&profile_set, &mut findings);
+        for prof in profile_set.profiles.values() {
+            check_profile(&profile_set, prof, &mut findings);
+        }
+        check_duplicates(&profile_set, &mut findings);
+        findings
+            .into_iter()
+            .map(|f| (f.severity, f.location.name, f.location.key))
+            .collect()
+    }
+
+    #[test]
+    fn a_complete_session_and_profile_have_no_findings() {
+        assert!(config_findings(SESSION).is_empty());
+    }
+
+    #[test]
+    fn broken_and_circular_chains_are_errors() {
+        let findings = config_findings(&format!(
+            "{}[profile orphan]\nrole_arn = arn:aws:iam::222222222222:role/Deploy\nsource_profile = missing\n\n\
+             [profile loop]\nrole_arn = arn:aws:iam::222222222222:role/Deploy\nsource_profile = loop\n",
+            SESSION
+        ));
+        let source_profile = Some("source_profile".to_string());
+        assert!(findings.contains(&(
+            Severity::Error,
+            "orphan".to_string(),
+            source_profile.clone()
+        )));
+        assert!(findings.contains(&(Severity::Error, "loop".to_string(), source_profile)));
+        assert_eq!(findings.len(), 2);
+    }
+
+    #[test]
+    fn missing_sessions_and_keys_are_errors() {
+        let findings = config_findings(
+            "[sso-session half]\nsso_region = eu-west-1\n\n\
+             [profile dev]\nsso_session = missing\nsso_account_id = 111111111111\n",
+        );
+        assert_eq!(
+            findings,
+            vec![
+                (Severity::Error, "half".to_string(), None),
+                (
+                    Severity::Error,
+                    "dev".to_string(),
+                    Some("sso_session".to_string())
+                ),
+                (Severity::Error, "dev".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn profiles_for_the_same_role_are_flagged_after_the_first() {
+        let findings = config_findings(&format!(
+            "{}[profile dev-copy]\nsso_session = my-sso\nsso_account_id = 111111111111\nsso_role_name = Admin\n",
+            SESSION
+        ));
+        assert_eq!(
+            findings,
+            vec![(Severity::Warning, "dev-copy".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn static_keys_on_an_sso_profile_are_flagged() {
+        let findings = config_findings(&format!(
+            "{}aws_access_key_id = AKIAEXAMPLE\naws_secret_access_key = secret\n",
+            SESSION
+        ));
+        assert_eq!(
+            findings,
+            vec![(
+                Severity::Warning,
+                "dev".to_string(),
+                Some("aws_access_key_id".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn repeated_sections_are_flagged() {
+        let findings = config_findings(&format!("{}[profile dev]\nregion = eu-west-1\n", SESSION));
+        assert_eq!(findings, vec![(Severity::Warning, "dev".to_string(), None)]);
+    }
+}
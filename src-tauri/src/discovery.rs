@@ -0,0 +1,175 @@
+use std::{fs, path::PathBuf};
+
+use aws_config::Region;
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aws::{config::AwsConfigSections, paths::butler_cache_dir},
+    cache::cache_key_hash,
+    login::generate_aws_config,
+    provider::get_valid_token,
+    trace_err_ret,
+};
+
+/// Discoveries younger than this are served from the cache unless a refresh
+/// is asked for, as listing every account's roles takes a request per account.
+const DISCOVERY_TTL: TimeDelta = TimeDelta::hours(1);
+
+/// How many accounts have their roles listed at the same time, the portal
+/// API throttles quickly.
+const ROLE_LISTING_CONCURRENCY: usize = 4;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct DiscoveredAccount {
+    pub(crate) account_id: String,
+    pub(crate) account_name: Option<String>,
+    pub(crate) email_address: Option<String>,
+    pub(crate) role_names: Vec<String>,
+}
+
+/// Every account and permission set the signed-in user of a session can use.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct SessionDiscovery {
+    pub(crate) session_name: String,
+    pub(crate) discovered_at: DateTime<Utc>,
+    pub(crate) accounts: Vec<DiscoveredAccount>,
+}
+
+fn discovery_cache_file(session_name: &str) -> Result<PathBuf, anyhow::Error> {
+    Ok(butler_cache_dir()?
+        .join("discovery")
+        .join(format!("{}.json", cache_key_hash(session_name))))
+}
+
+fn get_cached_discovery(session_name: &str) -> Option<SessionDiscovery> {
+    let content = fs::read_to_string(discovery_cache_file(session_name).ok()?).ok()?;
+    serde_json::from_str::<SessionDiscovery>(&content)
+        .ok()
+        .filter(|disc| Utc::now() - disc.discovered_at < DISCOVERY_TTL)
+}
+
+fn store_cached_discovery(discovery: &SessionDiscovery) -> Result<(), anyhow::Error> {
+    let cache_file = discovery_cache_file(&discovery.session_name)?;
+    if let Some(parent) = cache_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(cache_file, serde_json::to_string_pretty(discovery)?)?;
+    Ok(())
+}
+
+/// Drops the cached discovery of a session, e.g. after renaming or deleting it.
+pub(crate) fn remove_cached_discovery(session_name: &str) -> Result<(), anyhow::Error> {
+    let cache_file = discovery_cache_file(session_name)?;
+    if cache_file.exists() {
+        fs::remove_file(cache_file)?;
+    }
+    Ok(())
+}
+
+async fn list_account_roles(
+    sso_client: &aws_sdk_sso::Client,
+    access_token: &str,
+    account_id: &str,
+) -> Result<Vec<String>, anyhow::Error> {
+    let roles = sso_client
+        .list_account_roles()
+        .access_token(access_token)
+        .account_id(account_id)
+        .into_paginator()
+        .items()
+        .send()
+        .collect::<Result<Vec<_>, _>>()
+        .await?;
+    let mut role_names = roles
+        .iter()
+        .filter_map(|role| role.role_name().map(|name| name.to_string()))
+        .collect::<Vec<_>>();
+    role_names.sort();
+    Ok(role_names)
+}
+
+async fn list_accounts(
+    sso_client: &aws_sdk_sso::Client,
+    access_token: &str,
+) -> Result<Vec<DiscoveredAccount>, anyhow::Error> {
+    let accounts = sso_client
+        .list_accounts()
+        .access_token(access_token)
+        .into_paginator()
+        .items()
+        .send()
+        .collect::<Result<Vec<_>, _>>()
+        .await?;
+
+    let mut discovered = futures::stream::iter(accounts)
+        .map(|account| async move {
+            let account_id = account
+                .account_id()
+                .ok_or_else(|| trace_err_ret("Account ID missing from account list!"))?;
+            Ok::<_, anyhow::Error>(DiscoveredAccount {
+                account_id: account_id.to_string(),
+                account_name: account.account_name().map(|name| name.to_string()),
+                email_address: account.email_address().map(|email| email.to_string()),
+                role_names: list_account_roles(sso_client, access_token, account_id).await?,
+            })
+        })
+        .buffer_unordered(ROLE_LISTING_CONCURRENCY)
+        .try_collect::<Vec<_>>()
+        .await?;
+    discovered.sort_by(|a, b| {
+        a.account_name
+            .cmp(&b.account_name)
+            .then_with(|| a.account_id.cmp(&b.account_id))
+    });
+    Ok(discovered)
+}
+
+/// Lists the accounts and roles available through an sso-session, using its
+/// cached token so this never starts an interactive login. Results are cached
+/// per session, `refresh` skips the cache.
+pub(crate) async fn discover_session(
+    profile_set: &AwsConfigSections,
+    session_name: &str,
+    refresh: bool,
+) -> Result<SessionDiscovery, anyhow::Error> {
+    if !refresh && let Some(discovery) = get_cached_discovery(session_name) {
+        return Ok(discovery);
+    }
+
+    let session = profile_set
+        .sessions
+        .get(session_name)
+        .ok_or_else(|| trace_err_ret("Session not found!"))?;
+    let sso_region = session
+        .sso_region()
+        .ok_or_else(|| trace_err_ret("No region found for session!"))?;
+    let sso_start_url = session
+        .sso_start_url()
+        .ok_or_else(|| trace_err_ret("No start URL found for session!"))?;
+
+    let config = generate_aws_config(Region::new(sso_region.to_string())).await;
+    let sso_oidc_client = aws_sdk_ssooidc::Client::new(&config);
+    let token = get_valid_token(
+        &sso_oidc_client,
+        Some(session_name),
+        sso_region,
+        sso_start_url,
+    )
+    .await?;
+
+    let sso_client = aws_sdk_sso::Client::new(&config);
+    let discovery = SessionDiscovery {
+        session_name: session_name.to_string(),
+        discovered_at: Utc::now(),
+        accounts: list_accounts(&sso_client, &token.access_token).await?,
+    };
+    store_cached_discovery(&discovery)?;
+    tracing::info!(
+        "discovered {} accounts for session {}",
+        discovery.accounts.len(),
+        session_name
+    );
+    Ok(discovery)
+}
@@ -0,0 +1,439 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aws::{
+        config::AwsConfigSections,
+        credentials::{remove_credentials_for_profiles, rename_credentials_for_profile},
+        ini_edit::{
+            edit_file_locked, has_section, remove_keys, remove_sections, rename_section,
+            upsert_section,
+        },
+        paths::config_file,
+    },
+    cache::{remove_token_from_cache, rename_session_in_cache},
+    discovery::remove_cached_discovery,
+    provider::{remove_cached_credentials, rename_cached_credentials},
+    trace_err_ret,
+};
+
+/// The kind of config section being edited.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub(crate) enum SectionType {
+    SsoSession,
+    Profile,
+}
+
+/// The properties of a section as the editor shows them, nested blocks are
+/// left out and never touched by an update.
+pub(crate) type SectionProperties = BTreeMap<String, String>;
+
+/// The header a section of this type and name has in the config file. The
+/// default profile may be written either way, an existing header wins.
+fn section_header(contents: &str, section_type: SectionType, name: &str) -> String {
+    match section_type {
+        SectionType::SsoSession => format!("sso-session {}", name),
+        SectionType::Profile if name == "default" && !has_section(contents, "profile default") => {
+            "default".to_string()
+        }
+        SectionType::Profile => format!("profile {}", name),
+    }
+}
+
+fn section_exists(profile_set: &AwsConfigSections, section_type: SectionType, name: &str) -> bool {
+    match section_type {
+        SectionType::SsoSession => profile_set.sessions.contains_key(name),
+        SectionType::Profile => profile_set.profiles.contains_key(name),
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        Err("name must not be empty".to_string())
+    } else if name.contains(char::is_whitespace) || name.contains(['[', ']']) {
+        Err(format!(
+            "name {} must not contain whitespace or brackets",
+            name
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Accepts the shape of AWS region names, e.g. `eu-west-1` or
+/// `us-gov-east-1`, without pinning the list of regions.
+fn is_valid_region(region: &str) -> bool {
+    let parts = region.split('-').collect::<Vec<_>>();
+    parts.len() >= 3
+        && parts[0].len() == 2
+        && parts[..parts.len() - 1]
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase()))
+        && parts[parts.len() - 1]
+            .parse::<u8>()
+            .is_ok_and(|num| num > 0)
+}
+
+fn validate_start_url(start_url: &str) -> Result<(), String> {
+    match tauri::Url::parse(start_url) {
+        Ok(url) if url.scheme() == "https" && url.host_str().is_some() => Ok(()),
+        _ => Err(format!(
+            "sso_start_url {} must be an https URL, e.g. https://my-org.awsapps.com/start",
+            start_url
+        )),
+    }
+}
+
+fn validate_session(properties: &SectionProperties) -> Vec<String> {
+    let mut problems = Vec::new();
+    match properties.get("sso_start_url") {
+        Some(start_url) => problems.extend(validate_start_url(start_url).err()),
+        None => problems.push("sso_start_url is required".to_string()),
+    }
+    match properties.get("sso_region") {
+        Some(region) if !is_valid_region(region) => {
+            problems.push(format!("sso_region {} is not a valid region", region))
+        }
+        Some(_) => {}
+        None => problems.push("sso_region is required".to_string()),
+    }
+    problems
+}
+
+fn validate_profile(
+    profile_set: &AwsConfigSections,
+    name: &str,
+    properties: &SectionProperties,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    let has = |key: &str| properties.contains_key(key);
+
+    if let Some(region) = properties.get("region")
+        && !is_valid_region(region)
+    {
+        problems.push(format!("region {} is not a valid region", region));
+    }
+    if let Some(account_id) = properties.get("sso_account_id")
+        && !(account_id.len() == 12 && account_id.chars().all(|c| c.is_ascii_digit()))
+    {
+        problems.push(format!("sso_account_id {} must be 12 digits", account_id));
+    }
+
+    if let Some(session_name) = properties.get("sso_session") {
+        if !profile_set.sessions.contains_key(session_name) {
+            problems.push(format!("sso-session {} does not exist", session_name));
+        }
+        if has("sso_start_url") || has("sso_region") {
+            problems.push(
+                "sso_start_url and sso_region belong in the sso-session, not the profile"
+                    .to_string(),
+            );
+        }
+    } else if has("sso_start_url") || has("sso_region") {
+        // legacy profiles carry their own SSO settings
+        match properties.get("sso_start_url") {
+            Some(start_url) => problems.extend(validate_start_url(start_url).err()),
+            None => problems.push("sso_start_url is required with sso_region".to_string()),
+        }
+        match properties.get("sso_region") {
+            Some(region) if !is_valid_region(region) => {
+                problems.push(format!("sso_region {} is not a valid region", region))
+            }
+            Some(_) => {}
+            None => problems.push("sso_region is required with sso_start_url".to_string()),
+        }
+    }
+
+    let uses_sso = has("sso_session") || has("sso_start_url");
+    for key in ["sso_account_id", "sso_role_name"] {
+        if uses_sso && !has(key) {
+            problems.push(format!("{} is required for SSO profiles", key));
+        }
+    }
+
+    match (properties.get("role_arn"), properties.get("source_profile")) {
+        (Some(_), Some(source)) if source == name => {
+            problems.push("source_profile must not be the profile itself".to_string())
+        }
+        (Some(_), Some(source)) if !profile_set.profiles.contains_key(source) => {
+            problems.push(format!("source_profile {} does not exist", source))
+        }
+        (Some(role_arn), _) if !role_arn.starts_with("arn:") => {
+            problems.push(format!("role_arn {} is not an ARN", role_arn))
+        }
+        // roles can also be assumed with credentials from the environment or
+        // a web identity token
+        (Some(_), None) if !has("credential_source") && !has("web_identity_token_file") => problems
+            .push(
+                "role_arn needs a source_profile, credential_source or web_identity_token_file"
+                    .to_string(),
+            ),
+        (None, Some(_)) => problems.push("source_profile needs a role_arn".to_string()),
+        _ => {}
+    }
+    if has("source_profile") && has("credential_source") {
+        problems.push("source_profile and credential_source can't be used together".to_string());
+    }
+    problems
+}
+
+/// Checks a section before it's written, against the config as it is in the
+/// file. Every problem is reported at once so the form can be fixed in one go.
+fn validate_section(
+    profile_set: &AwsConfigSections,
+    section_type: SectionType,
+    name: &str,
+    properties: &SectionProperties,
+) -> Result<(), anyhow::Error> {
+    let mut problems = validate_name(name).err().into_iter().collect::<Vec<_>>();
+    for (key, value) in properties {
+        if key.is_empty() || key.contains(char::is_whitespace) || key.contains('=') {
+            problems.push(format!("{} is not a valid key", key));
+        } else if value.trim().is_empty() {
+            problems.push(format!("{} must not be empty", key));
+        }
+    }
+    problems.extend(match section_type {
+        SectionType::SsoSession => validate_session(properties),
+        SectionType::Profile => validate_profile(profile_set, name, properties),
+    });
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(trace_err_ret(&format!(
+            "Invalid {}: {}",
+            name,
+            problems.join("; ")
+        )))
+    }
+}
+
+/// Normalizes keys the way the parser reads them and trims values.
+fn normalize(properties: SectionProperties) -> SectionProperties {
+    properties
+        .into_iter()
+        .map(|(key, value)| (key.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect()
+}
+
+/// Reads back the flat properties of a section for editing.
+pub(crate) fn get_section(
+    profile_set: &AwsConfigSections,
+    section_type: SectionType,
+    name: &str,
+) -> Result<SectionProperties, anyhow::Error> {
+    let properties = match section_type {
+        SectionType::SsoSession => profile_set.sessions.get(name).map(|s| &s.properties),
+        SectionType::Profile => profile_set.profiles.get(name).map(|p| &p.properties),
+    }
+    .ok_or_else(|| trace_err_ret("Section not found!"))?;
+    Ok(properties
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect())
+}
+
+/// Adds a new section, refusing to overwrite an existing one.
+pub(crate) fn create_section(
+    section_type: SectionType,
+    name: &str,
+    properties: SectionProperties,
+) -> Result<(), anyhow::Error> {
+    let properties = normalize(properties);
+    edit_file_locked(&config_file()?, |contents| {
+        let current = AwsConfigSections::parse_str(contents);
+        if section_exists(&current, section_type, name) {
+            return Err(trace_err_ret(&format!("{} already exists!", name)));
+        }
+        validate_section(&current, section_type, name, &properties)?;
+        let pairs = properties
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        Ok(Some(upsert_section(
+            contents,
+            &section_header(contents, section_type, name),
+            &pairs,
+        )))
+    })
+}
+
+/// Replaces the properties of a section. Keys left out are removed, apart
+/// from nested blocks which the editor doesn't show. Cached process
+/// credentials of an edited profile are dropped, they may be for another
+/// account or role now.
+pub(crate) fn update_section(
+    section_type: SectionType,
+    name: &str,
+    properties: SectionProperties,
+) -> Result<(), anyhow::Error> {
+    let properties = normalize(properties);
+    edit_file_locked(&config_file()?, |contents| {
+        let current = AwsConfigSections::parse_str(contents);
+        let existing = get_section(&current, section_type, name)?;
+        validate_section(&current, section_type, name, &properties)?;
+
+        let header = section_header(contents, section_type, name);
+        // only the first section would be edited, a later one still wins
+        let section_count = match section_type {
+            SectionType::SsoSession => current.sessions.get(name).map(|s| s.section_count),
+            SectionType::Profile => current.profiles.get(name).map(|p| p.section_count),
+        };
+        if let Some(count) = section_count.filter(|count| *count > 1) {
+            return Err(trace_err_ret(&format!(
+                "[{}] appears {} times in the config, merge the sections as the diagnostics suggest before editing it!",
+                header, count
+            )));
+        }
+        let removed = existing
+            .keys()
+            .filter(|key| !properties.contains_key(*key))
+            .map(|key| key.as_str())
+            .collect::<Vec<_>>();
+        let pairs = properties
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        let contents = upsert_section(contents, &header, &pairs);
+        Ok(Some(
+            remove_keys(&contents, &header, &removed).unwrap_or(contents),
+        ))
+    })?;
+
+    if section_type == SectionType::Profile {
+        remove_cached_credentials(name)?;
+    }
+    Ok(())
+}
+
+/// Renames a section and points every reference to it at the new name. The
+/// credentials and cache entries of the old name move along, so nothing has
+/// to log in again.
+pub(crate) fn rename_config_section(
+    section_type: SectionType,
+    old_name: &str,
+    new_name: &str,
+) -> Result<(), anyhow::Error> {
+    let mut sso_start_url = None;
+    edit_file_locked(&config_file()?, |contents| {
+        let current = AwsConfigSections::parse_str(contents);
+        if !section_exists(&current, section_type, old_name) {
+            return Err(trace_err_ret(&format!("{} not found!", old_name)));
+        }
+        if section_exists(&current, section_type, new_name) {
+            return Err(trace_err_ret(&format!("{} already exists!", new_name)));
+        }
+        validate_name(new_name).map_err(|e| trace_err_ret(&e))?;
+
+        let old_header = section_header(contents, section_type, old_name);
+        let mut contents = rename_section(
+            contents,
+            &old_header,
+            &section_header("", section_type, new_name),
+        )
+        .ok_or_else(|| trace_err_ret("Section not found!"))?;
+
+        // update the profiles referring to the old name
+        let reference_key = match section_type {
+            SectionType::SsoSession => "sso_session",
+            SectionType::Profile => "source_profile",
+        };
+        let mut referring = current
+            .profiles
+            .values()
+            .filter(|prof| prof.get(reference_key) == Some(old_name))
+            .map(|prof| prof.name())
+            .collect::<Vec<_>>();
+        referring.sort();
+        for prof_name in referring {
+            let header = section_header(&contents, SectionType::Profile, prof_name);
+            contents = upsert_section(&contents, &header, &[(reference_key, new_name)]);
+        }
+
+        if section_type == SectionType::SsoSession {
+            sso_start_url = current
+                .sessions
+                .get(old_name)
+                .and_then(|s| s.sso_start_url())
+                .map(|url| url.to_string());
+        }
+        Ok(Some(contents))
+    })?;
+
+    match section_type {
+        SectionType::SsoSession => {
+            if let Some(sso_start_url) = sso_start_url {
+                rename_session_in_cache(old_name, new_name, &sso_start_url)?;
+            }
+            remove_cached_discovery(old_name)?;
+        }
+        SectionType::Profile => {
+            rename_credentials_for_profile(old_name, new_name)?;
+            rename_cached_credentials(old_name, new_name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Deletes a section along with the credentials and cache entries that only
+/// it used. Sections other profiles still refer to are kept.
+pub(crate) fn delete_section(section_type: SectionType, name: &str) -> Result<(), anyhow::Error> {
+    let mut sso_start_url = None;
+    edit_file_locked(&config_file()?, |contents| {
+        let current = AwsConfigSections::parse_str(contents);
+        if !section_exists(&current, section_type, name) {
+            return Err(trace_err_ret(&format!("{} not found!", name)));
+        }
+        let reference_key = match section_type {
+            SectionType::SsoSession => "sso_session",
+            SectionType::Profile => "source_profile",
+        };
+        let mut referring = current
+            .profiles
+            .values()
+            .filter(|prof| prof.get(reference_key) == Some(name))
+            .map(|prof| prof.name())
+            .collect::<Vec<_>>();
+        if !referring.is_empty() {
+            referring.sort();
+            return Err(trace_err_ret(&format!(
+                "{} is still used by {}!",
+                name,
+                referring.join(", ")
+            )));
+        }
+
+        if section_type == SectionType::SsoSession {
+            sso_start_url = current
+                .sessions
+                .get(name)
+                .and_then(|s| s.sso_start_url())
+                .map(|url| url.to_string());
+        }
+        let headers = match section_type {
+            SectionType::SsoSession => vec![format!("sso-session {}", name)],
+            SectionType::Profile if name == "default" => {
+                vec!["default".to_string(), "profile default".to_string()]
+            }
+            SectionType::Profile => vec![format!("profile {}", name)],
+        };
+        let headers = headers.iter().map(|h| h.as_str()).collect::<Vec<_>>();
+        Ok(remove_sections(contents, &headers))
+    })?;
+
+    match section_type {
+        SectionType::SsoSession => {
+            if let Some(sso_start_url) = sso_start_url {
+                remove_token_from_cache(Some(name), &sso_start_url)?;
+            }
+            remove_cached_discovery(name)?;
+        }
+        SectionType::Profile => {
+            remove_credentials_for_profiles(&[name])?;
+            remove_cached_credentials(name)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aws::{
+        config::{AwsConfigSections, Profile},
+        ini_edit::{edit_file_locked, upsert_section},
+        paths::config_file,
+    },
+    discovery::{SessionDiscovery, discover_session},
+    trace_err_ret,
+};
+
+/// How generated profiles are named and which defaults they get.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct GenerateOptions {
+    pub(crate) session_name: String,
+    /// e.g. `{account_name}-{role_name}`, see `render_profile_name`
+    pub(crate) template: String,
+    pub(crate) region: Option<String>,
+    pub(crate) output: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub(crate) enum PlannedAction {
+    /// The profile will be written.
+    Create,
+    /// A profile with this name already points at this account and role.
+    Unchanged,
+    /// A profile with this name exists with other settings and is kept.
+    NameTaken,
+    /// Another profile already uses this account and role.
+    AlreadyConfigured(String),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct PlannedProfile {
+    pub(crate) profile_name: String,
+    pub(crate) account_id: String,
+    pub(crate) role_name: String,
+    pub(crate) action: PlannedAction,
+}
+
+/// Fills in `{account_name}`, `{account_id}`, `{role_name}` and
+/// `{session_name}`. Whitespace becomes `-` as profile names can't hold it,
+/// and accounts without a name fall back to their ID.
+fn render_profile_name(
+    template: &str,
+    session_name: &str,
+    account_id: &str,
+    account_name: Option<&str>,
+    role_name: &str,
+) -> Result<String, anyhow::Error> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| trace_err_ret("Unclosed placeholder in naming template!"))?;
+        name.push_str(match &rest[start + 1..start + end] {
+            "account_name" => account_name.unwrap_or(account_id),
+            "account_id" => account_id,
+            "role_name" => role_name,
+            "session_name" => session_name,
+            other => {
+                return Err(trace_err_ret(&format!(
+                    "Unknown placeholder {{{}}} in naming template!",
+                    other
+                )));
+            }
+        });
+        rest = &rest[start + end + 1..];
+    }
+    name.push_str(rest);
+
+    let name = name
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+        .replace(['[', ']'], "");
+    if name.is_empty() {
+        return Err(trace_err_ret("Naming template produced an empty name!"));
+    }
+    Ok(name)
+}
+
+/// Works out which profiles the discovered roles would become, leaving every
+/// existing profile as it is.
+pub(crate) fn plan_profiles(
+    profile_set: &AwsConfigSections,
+    discovery: &SessionDiscovery,
+    options: &GenerateOptions,
+) -> Result<Vec<PlannedProfile>, anyhow::Error> {
+    let mut planned: Vec<PlannedProfile> = Vec::new();
+    for account in &discovery.accounts {
+        for role_name in &account.role_names {
+            let profile_name = render_profile_name(
+                &options.template,
+                &options.session_name,
+                &account.account_id,
+                account.account_name.as_deref(),
+                role_name,
+            )?;
+            let targets_role = |prof: &Profile| {
+                prof.sso_session() == Some(options.session_name.as_str())
+                    && prof.sso_account_id() == Some(account.account_id.as_str())
+                    && prof.sso_role_name() == Some(role_name.as_str())
+            };
+
+            let action = match profile_set.profiles.get(&profile_name) {
+                Some(existing) if targets_role(existing) => PlannedAction::Unchanged,
+                Some(_) => PlannedAction::NameTaken,
+                None if planned.iter().any(|p| p.profile_name == profile_name) => {
+                    PlannedAction::NameTaken
+                }
+                None => match profile_set.profiles.values().find(|p| targets_role(p)) {
+                    Some(existing) => PlannedAction::AlreadyConfigured(existing.name().to_string()),
+                    None => PlannedAction::Create,
+                },
+            };
+            planned.push(PlannedProfile {
+                profile_name,
+                account_id: account.account_id.clone(),
+                role_name: role_name.clone(),
+                action,
+            });
+        }
+    }
+    planned.sort_by(|a, b| a.profile_name.cmp(&b.profile_name));
+    Ok(planned)
+}
+
+/// Previews the profiles a template would generate for a session.
+pub(crate) async fn preview_profiles(
+    profile_set: &AwsConfigSections,
+    options: &GenerateOptions,
+) -> Result<Vec<PlannedProfile>, anyhow::Error> {
+    let discovery = discover_session(profile_set, &options.session_name, false).await?;
+    plan_profiles(profile_set, &discovery, options)
+}
+
+/// Writes the planned profiles named in `profile_names` to the config file.
+/// The plan is made again against the file as it is under the lock, so
+/// profiles added since the preview are never overwritten. Returns the names
+/// of the profiles written.
+pub(crate) async fn apply_profiles(
+    profile_set: &AwsConfigSections,
+    options: &GenerateOptions,
+    profile_names: &[String],
+) -> Result<Vec<String>, anyhow::Error> {
+    let discovery = discover_session(profile_set, &options.session_name, false).await?;
+    let mut written = Vec::new();
+    edit_file_locked(&config_file()?, |contents| {
+        let current = AwsConfigSections::parse_str(contents);
+        let mut contents = contents.to_string();
+        for planned in plan_profiles(&current, &discovery, options)? {
+            if planned.action != PlannedAction::Create
+                || !profile_names.contains(&planned.profile_name)
+            {
+                continue;
+            }
+            let mut properties = vec![
+                ("sso_session", options.session_name.as_str()),
+                ("sso_account_id", planned.account_id.as_str()),
+                ("sso_role_name", planned.role_name.as_str()),
+            ];
+            // blank defaults from the form mean "don't set"
+            for (key, value) in [("region", &options.region), ("output", &options.output)] {
+                if let Some(value) = value.as_deref().filter(|v| !v.trim().is_empty()) {
+                    properties.push((key, value.trim()));
+                }
+            }
+            contents = upsert_section(
+                &contents,
+                &format!("profile {}", planned.profile_name),
+                &properties,
+            );
+            written.push(planned.profile_name);
+        }
+        Ok((!written.is_empty()).then_some(contents))
+    })?;
+    tracing::info!(
+        "generated {} profiles for session {}",
+        written.len(),
+        options.session_name
+    );
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::discovery::DiscoveredAccount;
+
+    fn render(template: &str, account_name: Option<&str>) -> Result<String, anyhow::Error> {
+        render_profile_name(template, "my-sso", "111111111111", account_name, "Admin")
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        assert_eq!(
+            render(
+                "{session_name}/{account_name}-{account_id}-{role_name}",
+                Some("dev")
+            )
+            .unwrap(),
+            "my-sso/dev-111111111111-Admin"
+        );
+        assert_eq!(
+            render("{account_name}-{role_name}", None).unwrap(),
+            "111111111111-Admin"
+        );
+    }
+
+    #[test]
+    fn names_lose_whitespace_and_brackets() {
+        assert_eq!(
+            render("{account_name} {role_name}", Some(" My  Account [prod] ")).unwrap(),
+            "My-Account-prod-Admin"
+        );
+    }
+
+    #[test]
+    fn bad_templates_are_rejected() {
+        assert!(render("{account}-{role_name}", None).is_err());
+        assert!(render("{account_name", None).is_err());
+        assert!(render("[]", None).is_err());
+    }
+
+    #[test]
+    fn plan_classifies_every_role() {
+        let profile_set = AwsConfigSections::parse_str(
+            "[profile dev-Admin]\nsso_session = my-sso\nsso_account_id = 111111111111\nsso_role_name = Admin\n\n\
+             [profile dev-ReadOnly]\nsso_session = other-sso\nsso_account_id = 111111111111\nsso_role_name = ReadOnly\n\n\
+             [profile legacy-billing]\nsso_session = my-sso\nsso_account_id = 222222222222\nsso_role_name = Billing\n",
+        );
+        let account =
+            |account_id: &str, account_name: &str, role_names: &[&str]| DiscoveredAccount {
+                account_id: account_id.to_string(),
+                account_name: Some(account_name.to_string()),
+                email_address: None,
+                role_names: role_names.iter().map(|role| role.to_string()).collect(),
+            };
+        let discovery = SessionDiscovery {
+            session_name: "my-sso".to_string(),
+            discovered_at: Utc::now(),
+            accounts: vec![
+                account("111111111111", "dev", &["Admin", "ReadOnly", "Deploy"]),
+                account("222222222222", "prod", &["Billing"]),
+                // a second account with the same name collides with the first
+                account("333333333333", "dev", &["Deploy"]),
+            ],
+        };
+        let options = GenerateOptions {
+            session_name: "my-sso".to_string(),
+            template: "{account_name}-{role_name}".to_string(),
+            region: None,
+            output: None,
+        };
+
+        let planned = plan_profiles(&profile_set, &discovery, &options).unwrap();
+        let actions = planned
+            .iter()
+            .map(|p| (p.profile_name.as_str(), p.account_id.as_str(), &p.action))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            vec![
+                ("dev-Admin", "111111111111", &PlannedAction::Unchanged),
+                ("dev-Deploy", "111111111111", &PlannedAction::Create),
+                ("dev-Deploy", "333333333333", &PlannedAction::NameTaken),
+                ("dev-ReadOnly", "111111111111", &PlannedAction::NameTaken),
+                (
+                    "prod-Billing",
+                    "222222222222",
+                    &PlannedAction::AlreadyConfigured("legacy-billing".to_string())
+                ),
+            ]
+        );
+    }
+}
@@ -1,16 +1,17 @@
 use std::{
     str::FromStr,
-    time::{Duration, SystemTime},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::SystemTime,
 };
 
-use aws_config::Region;
-use aws_sdk_sso::operation::get_role_credentials::GetRoleCredentialsOutput;
-use aws_sdk_ssooidc::operation::{
-    register_client::RegisterClientOutput,
-    start_device_authorization::StartDeviceAuthorizationOutput,
-};
+use futures::FutureExt;
 use serde::{Deserialize, Serialize};
-use tauri::{State, WebviewUrl, WebviewWindowBuilder, WindowEvent};
+use tauri::{
+    Emitter, Manager, State, WebviewUrl, WebviewWindow, WebviewWindowBuilder, WindowEvent,
+};
 use tokio::sync::{
     Mutex,
     mpsc::{self, error::TryRecvError},
@@ -18,338 +19,538 @@ use tokio::sync::{
 
 use crate::{
     ButlerState,
-    aws::{
-        config::Profile,
-        credentials::{get_credentials_for_profile, store_credentials_for_profile},
+    aws::{config::AwsConfigSections, credentials::get_credentials_for_profile},
+    cache::get_token_from_cache,
+    diagnostics::{Finding, diagnose},
+    discovery::{SessionDiscovery, discover_session},
+    editor::{self, SectionProperties, SectionType},
+    fetch_profiles_new,
+    generator::{GenerateOptions, PlannedProfile, apply_profiles, preview_profiles},
+    inflight::{LoginTarget, SharedLogin},
+    login::{
+        AuthPrompt, LoginBrowser, LoginFlow, LoginStep, ProfileSelection, login_browser,
+        login_settings, profile_login, profile_logout, sso_session_login, sso_session_logout,
     },
-    cache::{get_token_from_cache, store_token_in_cache},
-    fetch_profiles_new, trace_err_ret,
+    outcome::LoginReport,
+    registration::load_registration,
+    settings::{self, Settings},
+    trace_err_ret,
+    tray::update_tray,
 };
 
-#[derive(Serialize, Deserialize, Debug)]
-pub(crate) struct SsoToken {
-    pub(crate) access_token: String,
-    pub(crate) refresh_token: Option<String>,
-    pub(crate) expiration: SystemTime,
+/// Every login gets its own window, so logins of different targets can run
+/// side by side.
+fn auth_window_label(login_id: u64) -> String {
+    format!("aws_authenticate_{}", login_id)
 }
 
-async fn generate_aws_config(region: Region) -> aws_config::SdkConfig {
-    aws_config::defaults(aws_config::BehaviorVersion::latest())
-        .region(region)
-        .load()
-        .await
+/// Sent to the main window as `login-progress` for every step of a login.
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct LoginProgress {
+    pub(crate) login_id: u64,
+    pub(crate) login_type: LoginType,
+    pub(crate) name: String,
+    pub(crate) step: LoginStep,
 }
 
-async fn create_registered_client(
-    sso_oidc_client: &aws_sdk_ssooidc::Client,
-) -> Result<RegisterClientOutput, anyhow::Error> {
-    Ok(sso_oidc_client
-        .register_client()
-        .client_name("aws-awth-butler")
-        .client_type("public")
-        .scopes("sso:account:access")
-        .send()
-        .await?)
-}
-
-async fn run_client_authorization(
-    sso_oidc_client: &aws_sdk_ssooidc::Client,
-    reg_cli: &RegisterClientOutput,
-    start_url: &str,
-) -> Result<StartDeviceAuthorizationOutput, anyhow::Error> {
-    Ok(sso_oidc_client
-        .start_device_authorization()
-        .client_id(
-            reg_cli
-                .client_id()
-                .ok_or_else(|| trace_err_ret("Client ID not found!"))?,
-        )
-        .client_secret(
-            reg_cli
-                .client_secret()
-                .ok_or_else(|| trace_err_ret("Client Secret not found!"))?,
-        )
-        .start_url(start_url)
-        .send()
-        .await?)
+pub(crate) fn emit_progress(
+    app_handle: &tauri::AppHandle,
+    login_id: u64,
+    target: &LoginTarget,
+    step: LoginStep,
+) {
+    let progress = LoginProgress {
+        login_id,
+        login_type: target.login_type.clone(),
+        name: target.name.clone(),
+        step,
+    };
+    if let Err(e) = app_handle.emit_to("main", "login-progress", progress) {
+        tracing::error!("emit error: {:?}", e);
+    }
 }
 
-async fn execute_login_flow(
+/// Shows the login's authorization page in an embedded webview window or a
+/// browser. A browser gives no sign of the user giving up, so logins there
+/// run until approved, timed out or cancelled from the main window.
+pub(crate) struct WebviewPrompt {
     app_handle: tauri::AppHandle,
-    auth_out: &StartDeviceAuthorizationOutput,
-    sso_oidc_client: &aws_sdk_ssooidc::Client,
-    reg_cli: &RegisterClientOutput,
-) -> Result<SsoToken, anyhow::Error> {
-    let auth_window = WebviewWindowBuilder::new(
-        &app_handle,
-        "aws_authenticate",
-        WebviewUrl::External(tauri::Url::from_str(
-            auth_out
-                .verification_uri_complete()
-                .ok_or_else(|| trace_err_ret("No verification uri after client registration!"))?,
-        )?),
-    )
-    .title("AWS Authenticate")
-    .inner_size(650.0, 800.0)
-    .min_inner_size(650.0, 800.0)
-    .build()?;
-
-    let (tx, mut rx) = mpsc::channel(1);
-    auth_window.on_window_event(move |event| {
-        let tx = tx.clone();
-        if let WindowEvent::CloseRequested { .. } = event {
-            tx.try_send(()).unwrap_or(());
+    login_id: u64,
+    target: LoginTarget,
+    cancelled: Arc<AtomicBool>,
+    browser: LoginBrowser,
+    window: Option<WebviewWindow>,
+    closed_rx: Option<mpsc::Receiver<()>>,
+}
+
+impl WebviewPrompt {
+    pub(crate) fn new(
+        app_handle: tauri::AppHandle,
+        login_id: u64,
+        target: LoginTarget,
+        cancelled: Arc<AtomicBool>,
+        browser: LoginBrowser,
+    ) -> Self {
+        Self {
+            app_handle,
+            login_id,
+            target,
+            cancelled,
+            browser,
+            window: None,
+            closed_rx: None,
         }
-    });
+    }
+}
 
-    let mut token: Option<SsoToken> = None;
-    let interval = auth_out.interval() as u64;
-    for _ in 0..(60 / interval) {
-        match rx.try_recv() {
-            Ok(_) => {
-                return Err(trace_err_ret("User closed window before authenticating!"));
+impl AuthPrompt for WebviewPrompt {
+    fn open(
+        &mut self,
+        verification_uri: &str,
+        _user_code: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        match &self.browser {
+            LoginBrowser::Embedded => {}
+            LoginBrowser::Named(browser) => {
+                webbrowser::open_browser(*browser, verification_uri)?;
+                return Ok(());
             }
-            Err(TryRecvError::Empty) => {}
-            Err(TryRecvError::Disconnected) => {
-                return Err(trace_err_ret(
-                    "Process exited mid authentication for some reason!",
-                ));
+            LoginBrowser::Command(command) => {
+                let (program, args) = command
+                    .split_first()
+                    .ok_or_else(|| trace_err_ret("Login browser command is empty!"))?;
+                let mut child = std::process::Command::new(program)
+                    .args(args)
+                    .arg(verification_uri)
+                    .spawn()?;
+                // browsers hand the URL to a running instance and exit, reap
+                // the launcher so it doesn't linger as a zombie
+                std::thread::spawn(move || {
+                    if let Err(e) = child.wait() {
+                        tracing::warn!("login browser command failed: {:?}", e);
+                    }
+                });
+                return Ok(());
             }
         }
-        if let Ok(output) = sso_oidc_client
-            .create_token()
-            .client_id(
-                reg_cli
-                    .client_id()
-                    .ok_or_else(|| trace_err_ret("Client ID not found!"))?,
-            )
-            .client_secret(
-                reg_cli
-                    .client_secret()
-                    .ok_or_else(|| trace_err_ret("Client Secret not found!"))?,
-            )
-            .grant_type("urn:ietf:params:oauth:grant-type:device_code")
-            .device_code(
-                auth_out
-                    .device_code()
-                    .ok_or_else(|| trace_err_ret("Device code not found!"))?,
-            )
-            .send()
-            .await
-        {
-            token = Some(SsoToken {
-                access_token: output
-                    .access_token()
-                    .ok_or_else(|| trace_err_ret("Access token missing from completed auth!"))?
-                    .to_string(),
-                refresh_token: output.refresh_token().map(|s| s.to_string()),
-                expiration: SystemTime::now() + Duration::from_secs(output.expires_in() as u64),
-            });
-            break;
-        }
-        tokio::time::sleep(Duration::from_secs(interval)).await;
-    }
-
-    if auth_window.is_closable()? {
-        auth_window.close()?
-    }
 
-    token.ok_or_else(|| trace_err_ret("Unable to complete SSO login flow!"))
-}
+        let auth_window = WebviewWindowBuilder::new(
+            &self.app_handle,
+            auth_window_label(self.login_id),
+            WebviewUrl::External(tauri::Url::from_str(verification_uri)?),
+        )
+        .title("AWS Authenticate")
+        .inner_size(650.0, 800.0)
+        .min_inner_size(650.0, 800.0)
+        .build()?;
+
+        let (tx, rx) = mpsc::channel(1);
+        auth_window.on_window_event(move |event| {
+            let tx = tx.clone();
+            if let WindowEvent::CloseRequested { .. } = event {
+                tx.try_send(()).unwrap_or(());
+            }
+        });
 
-async fn inner_sso_session_login(
-    app_handle: tauri::AppHandle,
-    state: State<'_, Mutex<ButlerState>>,
-    session_name: &str,
-) -> Result<(), anyhow::Error> {
-    // grab session information from config, if it exists
-    let profile_set = &state.lock().await.aws_profiles;
-    // println!("{:#?}", profile_set);
-    let session = profile_set
-        .sessions
-        .get(session_name)
-        .ok_or_else(|| trace_err_ret("Session not found!"))?;
-    let sso_region = session
-        .get("sso_region")
-        .ok_or_else(|| trace_err_ret("No region found for session!"))?;
-    let region = Region::new(sso_region.to_string());
-
-    // generate AWS config and clients
-    let config = generate_aws_config(region).await;
-    let sso_oidc_client = aws_sdk_ssooidc::Client::new(&config);
-    let reg_cli = create_registered_client(&sso_oidc_client).await?;
-
-    // run the client authorization flow
-    let sso_start_url = session
-        .get("sso_start_url")
-        .ok_or_else(|| trace_err_ret("No start URL found for session!"))?;
-    let response = run_client_authorization(&sso_oidc_client, &reg_cli, sso_start_url).await?;
-    let token = execute_login_flow(app_handle, &response, &sso_oidc_client, &reg_cli).await?;
-
-    // store the token in the cache
-    store_token_in_cache(
-        Some(session_name),
-        sso_start_url,
-        sso_region,
-        &reg_cli,
-        &token,
-    )?;
-
-    // find all profiles that use this session
-    let sso_client = aws_sdk_sso::Client::new(&config);
-    let profiles = &profile_set.profiles;
-    let profiles_using_session: Vec<&Profile> = profiles
-        .iter()
-        .flat_map(|prof_name| profile_set.profiles.get(prof_name.0))
-        .filter(|prof| prof.get("sso_session") == Some(session_name))
-        .collect();
+        self.window = Some(auth_window);
+        self.closed_rx = Some(rx);
+        Ok(())
+    }
 
-    // spawn off tasks to get role credentials for each profile in parallel
-    let tasks: Vec<_> = profiles_using_session
-        .iter()
-        .map(|p| async {
-            let creds = sso_client
-                .get_role_credentials()
-                .account_id(
-                    p.get("sso_account_id")
-                        .ok_or_else(|| trace_err_ret("No account ID found for profile!"))?,
-                )
-                .role_name(
-                    p.get("sso_role_name")
-                        .ok_or_else(|| trace_err_ret("No role name found for profile!"))?,
-                )
-                .access_token(&token.access_token)
-                .send()
-                .await?;
-            Ok::<(&str, GetRoleCredentialsOutput), anyhow::Error>((p.name(), creds))
-        })
-        .collect();
-
-    // collect the results from the tasks
-    let prof_creds = futures::future::join_all(tasks)
-        .await
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()?;
+    fn check_cancelled(&mut self) -> Result<(), anyhow::Error> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(trace_err_ret("Login was cancelled!"));
+        }
+        let Some(rx) = self.closed_rx.as_mut() else {
+            return Ok(());
+        };
+        match rx.try_recv() {
+            Ok(_) => Err(trace_err_ret("User closed window before authenticating!")),
+            Err(TryRecvError::Empty) => Ok(()),
+            Err(TryRecvError::Disconnected) => Err(trace_err_ret(
+                "Process exited mid authentication for some reason!",
+            )),
+        }
+    }
 
-    // update the credentials file with the new role credentials
-    // don't want to do this in parallel in case of file contention
-    for (prof_name, creds) in prof_creds {
-        store_credentials_for_profile(prof_name, &creds)?;
+    fn close(&mut self) -> Result<(), anyhow::Error> {
+        if let Some(auth_window) = self.window.take()
+            && auth_window.is_closable()?
+        {
+            auth_window.close()?
+        }
+        Ok(())
     }
 
-    // println!("finished doing auth thing!");
-    Ok(())
+    fn progress(&mut self, step: LoginStep) {
+        emit_progress(&self.app_handle, self.login_id, &self.target, step);
+    }
 }
 
-async fn inner_legacy_profile_login(
+#[tauri::command]
+pub(crate) async fn refresh_profiles(
     app_handle: tauri::AppHandle,
     state: State<'_, Mutex<ButlerState>>,
-    profile_name: &str,
-) -> Result<(), anyhow::Error> {
-    let profile_set = &state.lock().await.aws_profiles;
-    let prof = profile_set
-        .profiles
-        .get(profile_name)
-        .ok_or_else(|| trace_err_ret("Profile not found!"))?;
-    let sso_region = prof
-        .get("sso_region")
-        .ok_or_else(|| trace_err_ret("No sso_region found for profile!"))?;
-    let region = Region::new(sso_region.to_string());
-    let config = generate_aws_config(region).await;
-    let sso_oidc_client = aws_sdk_ssooidc::Client::new(&config);
-    let reg_cli = create_registered_client(&sso_oidc_client).await?;
-    let sso_start_url = prof
-        .get("sso_start_url")
-        .ok_or_else(|| trace_err_ret("No sso_start_url found for profile!"))?;
-    let response = run_client_authorization(
-        &sso_oidc_client,
-        &reg_cli,
-        prof.get("sso_start_url")
-            .ok_or_else(|| trace_err_ret("No sso_start_url found for profile!"))?,
-    )
-    .await?;
-
-    let token = execute_login_flow(app_handle, &response, &sso_oidc_client, &reg_cli).await?;
-    store_token_in_cache(None, sso_start_url, sso_region, &reg_cli, &token)?;
-    let sso_client = aws_sdk_sso::Client::new(&config);
-    let creds = sso_client
-        .get_role_credentials()
-        .account_id(prof.get("sso_account_id").unwrap())
-        .role_name(prof.get("sso_role_name").unwrap())
-        .access_token(token.access_token)
-        .send()
-        .await?;
-    store_credentials_for_profile(profile_name, &creds)?;
-    Ok(())
-}
-
-#[tauri::command]
-pub(crate) async fn refresh_profiles(state: State<'_, Mutex<ButlerState>>) -> Result<(), String> {
+) -> Result<(), String> {
     let mut state = state.lock().await;
     state.aws_profiles = fetch_profiles_new().map_err(|e| e.to_string())?;
     // println!("{:#?}", state.aws_profiles);
+    update_tray(&app_handle);
     Ok(())
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
 pub(crate) enum LoginType {
     SsoSession,
     LegacyProfile,
 }
 
+/// Runs a login in the background on a snapshot of the config, so the state
+/// isn't locked while the user approves it. `prompt` is how it reaches the
+/// user, a window for logins from the app. The login leaves the registry once
+/// it's over, however it ended, and the main window hears how.
+pub(crate) fn spawn_login<P>(
+    app_handle: tauri::AppHandle,
+    profile_set: AwsConfigSections,
+    settings: Settings,
+    target: LoginTarget,
+    login_id: u64,
+    mut prompt: P,
+) -> SharedLogin
+where
+    P: AuthPrompt + Send + 'static,
+{
+    let handle = tauri::async_runtime::spawn(async move {
+        let report = match target.login_type {
+            LoginType::SsoSession => {
+                sso_session_login(
+                    &profile_set,
+                    &settings,
+                    &target.name,
+                    &target.selection,
+                    &mut prompt,
+                )
+                .await
+            }
+            LoginType::LegacyProfile => {
+                profile_login(&profile_set, &settings, &target.name, &mut prompt).await
+            }
+        };
+        let state = app_handle.state::<Mutex<ButlerState>>();
+        state.lock().await.logins.finish(login_id);
+
+        let step = match &report {
+            Ok(report) => {
+                let failed = report.failures().count();
+                LoginStep::Done {
+                    succeeded: report.profiles.len() - failed,
+                    failed,
+                }
+            }
+            Err(e) => LoginStep::Failed {
+                error: e.to_string(),
+            },
+        };
+        emit_progress(&app_handle, login_id, &target, step);
+        report.map_err(|e| e.to_string())
+    });
+    async move { handle.await.map_err(|e| e.to_string())? }
+        .boxed()
+        .shared()
+}
+
+/// Starts a login for `target`, or joins the one already running for its
+/// session or profile and brings its window to the front rather than opening
+/// a second one. A running login selecting other profiles can't be joined,
+/// the request fails until it's over.
+pub(crate) async fn run_login(
+    app_handle: tauri::AppHandle,
+    state: State<'_, Mutex<ButlerState>>,
+    target: LoginTarget,
+) -> Result<LoginReport, String> {
+    let login = {
+        let mut state = state.lock().await;
+        match state.logins.find(&target) {
+            Some((_, running, _))
+                if running.selection != ProfileSelection::All
+                    && running.selection != target.selection =>
+            {
+                return Err(format!(
+                    "{} is already logging in with other profiles selected, try again once that login is done",
+                    target.name
+                ));
+            }
+            Some((login_id, _, login)) => {
+                tracing::info!("joining the login in progress for {}", target.name);
+                if let Some(window) = app_handle.get_webview_window(&auth_window_label(login_id))
+                    && let Err(e) = window.set_focus()
+                {
+                    tracing::warn!("unable to focus the login window: {:?}", e);
+                }
+                login
+            }
+            None => {
+                let profile_set = state.aws_profiles.clone();
+                let settings = state.settings.clone();
+                state.logins.start(target.clone(), |login_id, cancelled| {
+                    let browser = match target.login_type {
+                        LoginType::SsoSession => {
+                            login_browser(&profile_set, &settings, None, Some(&target.name))
+                        }
+                        LoginType::LegacyProfile => login_browser(
+                            &profile_set,
+                            &settings,
+                            profile_set.profiles.get(&target.name),
+                            None,
+                        ),
+                    };
+                    let prompt = WebviewPrompt::new(
+                        app_handle.clone(),
+                        login_id,
+                        target.clone(),
+                        cancelled,
+                        browser,
+                    );
+                    spawn_login(app_handle, profile_set, settings, target, login_id, prompt)
+                })
+            }
+        }
+    };
+    login.await
+}
+
 #[tauri::command]
 pub(crate) async fn authenticate_aws(
     app_handle: tauri::AppHandle,
     state: State<'_, Mutex<ButlerState>>,
     login_type: LoginType,
     name: &str,
+) -> Result<LoginReport, String> {
+    let target = LoginTarget {
+        login_type,
+        name: name.to_string(),
+        selection: ProfileSelection::All,
+    };
+    run_login(app_handle, state, target).await
+}
+
+/// Logs into a session but only writes credentials for the selected profiles.
+#[tauri::command]
+pub(crate) async fn authenticate_session_profiles(
+    app_handle: tauri::AppHandle,
+    state: State<'_, Mutex<ButlerState>>,
+    session_name: &str,
+    selection: ProfileSelection,
+) -> Result<LoginReport, String> {
+    let target = LoginTarget {
+        login_type: LoginType::SsoSession,
+        name: session_name.to_string(),
+        selection,
+    };
+    run_login(app_handle, state, target).await
+}
+
+/// Stops the logins in progress for a session or profile and closes their
+/// windows. Their callers get a cancelled error.
+#[tauri::command]
+pub(crate) async fn cancel_login(
+    state: State<'_, Mutex<ButlerState>>,
+    login_type: LoginType,
+    name: &str,
+) -> Result<(), String> {
+    let cancelled = state.lock().await.logins.cancel(&login_type, name);
+    tracing::info!("cancelled {} logins for {}", cancelled, name);
+    Ok(())
+}
+
+#[tauri::command]
+pub(crate) async fn logout(
+    state: State<'_, Mutex<ButlerState>>,
+    login_type: LoginType,
+    name: &str,
 ) -> Result<(), String> {
+    let profile_set = state.lock().await.aws_profiles.clone();
     match login_type {
-        LoginType::SsoSession => inner_sso_session_login(app_handle, state, name)
+        LoginType::SsoSession => sso_session_logout(&profile_set, name)
             .await
             .map_err(|e| e.to_string()),
-        LoginType::LegacyProfile => inner_legacy_profile_login(app_handle, state, name)
+        LoginType::LegacyProfile => profile_logout(&profile_set, name)
             .await
             .map_err(|e| e.to_string()),
     }
 }
 
+/// Lists the accounts and roles of a session, from the cache unless `refresh`.
+#[tauri::command]
+pub(crate) async fn discover_accounts(
+    state: State<'_, Mutex<ButlerState>>,
+    session_name: &str,
+    refresh: bool,
+) -> Result<SessionDiscovery, String> {
+    let profile_set = state.lock().await.aws_profiles.clone();
+    discover_session(&profile_set, session_name, refresh)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Shows which profiles a naming template would add for a session.
+#[tauri::command]
+pub(crate) async fn preview_generated_profiles(
+    state: State<'_, Mutex<ButlerState>>,
+    options: GenerateOptions,
+) -> Result<Vec<PlannedProfile>, String> {
+    let profile_set = state.lock().await.aws_profiles.clone();
+    preview_profiles(&profile_set, &options)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Writes the previewed profiles the user kept, returning the ones written.
+#[tauri::command]
+pub(crate) async fn generate_profiles(
+    state: State<'_, Mutex<ButlerState>>,
+    options: GenerateOptions,
+    profile_names: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let profile_set = state.lock().await.aws_profiles.clone();
+    let written = apply_profiles(&profile_set, &options, &profile_names)
+        .await
+        .map_err(|e| e.to_string())?;
+    reload_profiles(&state).await?;
+    Ok(written)
+}
+
+/// Lints the loaded config for problems that would break or degrade logins.
+#[tauri::command]
+pub(crate) async fn diagnose_config(
+    state: State<'_, Mutex<ButlerState>>,
+) -> Result<Vec<Finding>, String> {
+    let profile_set = &state.lock().await.aws_profiles;
+    Ok(diagnose(profile_set))
+}
+
+/// Rereads the config into the state after butler changed the file, rather
+/// than waiting for the watcher to make the frontend do it.
+async fn reload_profiles(state: &State<'_, Mutex<ButlerState>>) -> Result<(), String> {
+    state.lock().await.aws_profiles = fetch_profiles_new().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub(crate) async fn get_config_section(
+    state: State<'_, Mutex<ButlerState>>,
+    section_type: SectionType,
+    name: &str,
+) -> Result<SectionProperties, String> {
+    let profile_set = &state.lock().await.aws_profiles;
+    editor::get_section(profile_set, section_type, name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub(crate) async fn create_config_section(
+    state: State<'_, Mutex<ButlerState>>,
+    section_type: SectionType,
+    name: &str,
+    properties: SectionProperties,
+) -> Result<(), String> {
+    editor::create_section(section_type, name, properties).map_err(|e| e.to_string())?;
+    reload_profiles(&state).await
+}
+
+#[tauri::command]
+pub(crate) async fn update_config_section(
+    state: State<'_, Mutex<ButlerState>>,
+    section_type: SectionType,
+    name: &str,
+    properties: SectionProperties,
+) -> Result<(), String> {
+    editor::update_section(section_type, name, properties).map_err(|e| e.to_string())?;
+    reload_profiles(&state).await
+}
+
+#[tauri::command]
+pub(crate) async fn rename_config_section(
+    state: State<'_, Mutex<ButlerState>>,
+    section_type: SectionType,
+    old_name: &str,
+    new_name: &str,
+) -> Result<(), String> {
+    editor::rename_config_section(section_type, old_name, new_name).map_err(|e| e.to_string())?;
+    reload_profiles(&state).await
+}
+
+#[tauri::command]
+pub(crate) async fn delete_config_section(
+    state: State<'_, Mutex<ButlerState>>,
+    section_type: SectionType,
+    name: &str,
+) -> Result<(), String> {
+    editor::delete_section(section_type, name).map_err(|e| e.to_string())?;
+    reload_profiles(&state).await
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct ButlerSsoSession {
-    session_name: String,
-    session_expiration: Option<chrono::DateTime<chrono::Utc>>,
-    fresh: bool,
-    profile_names: Vec<String>,
+    pub(crate) session_name: String,
+    pub(crate) session_expiration: Option<chrono::DateTime<chrono::Utc>>,
+    pub(crate) fresh: bool,
+    pub(crate) profile_names: Vec<String>,
+    pub(crate) registration_expiration: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct ButlerSsoProfile {
-    profile_name: String,
-    session_name: String,
-    profile_expiration: Option<chrono::DateTime<chrono::Utc>>,
-    fresh: bool,
+    pub(crate) profile_name: String,
+    pub(crate) session_name: String,
+    pub(crate) profile_expiration: Option<chrono::DateTime<chrono::Utc>>,
+    pub(crate) fresh: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct ButlerSsoLegacyProfile {
-    profile_name: String,
-    profile_expiration: Option<chrono::DateTime<chrono::Utc>>,
-    fresh: bool,
+    pub(crate) profile_name: String,
+    pub(crate) profile_expiration: Option<chrono::DateTime<chrono::Utc>>,
+    pub(crate) fresh: bool,
+    pub(crate) registration_expiration: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct ButlerChainedProfile {
+    pub(crate) profile_name: String,
+    pub(crate) source_profile: String,
+    /// The SSO profile the role chain starts at.
+    pub(crate) root_profile: String,
+    pub(crate) profile_expiration: Option<chrono::DateTime<chrono::Utc>>,
+    pub(crate) fresh: bool,
+}
+
+/// Looks up when the stored client registration for these settings expires,
+/// a missing or unreadable registration just reports as `None`.
+fn registration_expiration(
+    sso_region: Option<&str>,
+    sso_start_url: Option<&str>,
+    flow: LoginFlow,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    load_registration(sso_region?, sso_start_url?, flow)
+        .ok()
+        .flatten()
+        .map(|reg| reg.expires_at)
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct ButlerSsoConfig {
-    sessions: Vec<ButlerSsoSession>,
-    sso_profiles: Vec<ButlerSsoProfile>,
-    legacy_profiles: Vec<ButlerSsoLegacyProfile>,
+    pub(crate) sessions: Vec<ButlerSsoSession>,
+    pub(crate) sso_profiles: Vec<ButlerSsoProfile>,
+    pub(crate) legacy_profiles: Vec<ButlerSsoLegacyProfile>,
+    pub(crate) chained_profiles: Vec<ButlerChainedProfile>,
 }
 
-#[tauri::command]
-pub(crate) async fn fetch_butler_config(
-    state: State<'_, Mutex<ButlerState>>,
-) -> Result<ButlerSsoConfig, String> {
-    // println!("Fetching butler config...");
-    let state = &state.lock().await.aws_profiles;
+/// Builds the freshness overview of every session and profile, shared by the
+/// frontend and the CLI.
+pub(crate) fn build_butler_config(
+    state: &AwsConfigSections,
+    settings: &Settings,
+) -> Result<ButlerSsoConfig, anyhow::Error> {
     let sessions = &state
         .sessions
         .iter()
@@ -363,16 +564,12 @@ pub(crate) async fn fetch_butler_config(
 
     let session_profiles = profiles
         .iter()
-        .filter(|prof| prof.get("sso_session").is_some())
+        .filter(|prof| prof.sso_session().is_some())
         .collect::<Vec<_>>();
 
     let legacy_profiles = profiles
         .iter()
-        .filter(|prof| {
-            prof.get("sso_session").is_none()
-                && prof.get("sso_region").is_some()
-                && prof.get("sso_start_url").is_some()
-        })
+        .filter(|prof| prof.is_legacy_sso())
         .collect::<Vec<_>>();
 
     // println!("fetched sessions, session profiles and legacy profiles... Now making config");
@@ -383,26 +580,31 @@ pub(crate) async fn fetch_butler_config(
                 let cached_token = get_token_from_cache(sn)?;
                 let sso_exp = cached_token.map(|tok| tok.expiration);
                 let sso_fresh = sso_exp.map(|exp| exp > SystemTime::now()).unwrap_or(false);
+                let session = state.sessions.get(*sn);
                 Ok::<_, anyhow::Error>(ButlerSsoSession {
                     session_name: sn.to_string(),
                     session_expiration: sso_exp.map(|exp| exp.into()),
                     fresh: sso_fresh,
                     profile_names: session_profiles
                         .iter()
-                        .filter(|prof| prof.get("sso_session") == Some(sn))
+                        .filter(|prof| prof.sso_session() == Some(sn))
                         .map(|prof| prof.name().to_string())
                         .collect(),
+                    registration_expiration: registration_expiration(
+                        session.and_then(|s| s.sso_region()),
+                        session.and_then(|s| s.sso_start_url()),
+                        login_settings(state, settings, None, Some(*sn)).flow,
+                    ),
                 })
             })
-            .collect::<Result<Vec<_>, anyhow::Error>>()
-            .map_err(|e| e.to_string())?,
+            .collect::<Result<Vec<_>, anyhow::Error>>()?,
         sso_profiles: session_profiles
             .iter()
             .map(|prof| {
                 let cached_creds = get_credentials_for_profile(prof.name())?;
                 let prof_exp = cached_creds.map(|creds| creds.expiration);
                 let sess_name = prof
-                    .get("sso_session")
+                    .sso_session()
                     .ok_or_else(|| trace_err_ret("No session name found!"))?;
                 let prof_fresh = prof_exp
                     .map(|exp| exp > chrono::Utc::now())
@@ -414,8 +616,7 @@ pub(crate) async fn fetch_butler_config(
                     fresh: prof_fresh,
                 })
             })
-            .collect::<Result<Vec<_>, anyhow::Error>>()
-            .map_err(|e| e.to_string())?,
+            .collect::<Result<Vec<_>, anyhow::Error>>()?,
         legacy_profiles: legacy_profiles
             .iter()
             .map(|prof| {
@@ -428,10 +629,63 @@ pub(crate) async fn fetch_butler_config(
                     profile_name: prof.name().to_string(),
                     profile_expiration: prof_exp,
                     fresh: prof_fresh,
+                    registration_expiration: registration_expiration(
+                        prof.sso_region(),
+                        prof.sso_start_url(),
+                        login_settings(state, settings, Some(**prof), None).flow,
+                    ),
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?,
+        chained_profiles: profiles
+            .iter()
+            .filter(|prof| prof.is_chained())
+            // only chains butler can log in are shown
+            .filter_map(|prof| Some((prof, state.chain_root(prof)?)))
+            .map(|(prof, root)| {
+                let cached_creds = get_credentials_for_profile(prof.name())?;
+                let prof_exp = cached_creds.map(|creds| creds.expiration);
+                let prof_fresh = prof_exp
+                    .map(|exp| exp > chrono::Utc::now())
+                    .unwrap_or(false);
+                Ok::<_, anyhow::Error>(ButlerChainedProfile {
+                    profile_name: prof.name().to_string(),
+                    source_profile: prof.source_profile().unwrap_or_default().to_string(),
+                    root_profile: root.name().to_string(),
+                    profile_expiration: prof_exp,
+                    fresh: prof_fresh,
                 })
             })
-            .collect::<Result<Vec<_>, anyhow::Error>>()
-            .map_err(|e| e.to_string())?,
+            .collect::<Result<Vec<_>, anyhow::Error>>()?,
     };
     Ok(config)
 }
+
+#[tauri::command]
+pub(crate) async fn fetch_butler_config(
+    state: State<'_, Mutex<ButlerState>>,
+) -> Result<ButlerSsoConfig, String> {
+    // println!("Fetching butler config...");
+    let state = state.lock().await;
+    build_butler_config(&state.aws_profiles, &state.settings).map_err(|e| e.to_string())
+}
+
+/// Rereads the settings file, so edits made by hand show up.
+#[tauri::command]
+pub(crate) async fn load_settings(
+    state: State<'_, Mutex<ButlerState>>,
+) -> Result<Settings, String> {
+    let loaded = settings::load_settings().map_err(|e| e.to_string())?;
+    state.lock().await.settings = loaded.clone();
+    Ok(loaded)
+}
+
+#[tauri::command]
+pub(crate) async fn save_settings(
+    state: State<'_, Mutex<ButlerState>>,
+    settings: Settings,
+) -> Result<(), String> {
+    settings::store_settings(&settings).map_err(|e| e.to_string())?;
+    state.lock().await.settings = settings;
+    Ok(())
+}
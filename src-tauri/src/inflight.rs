@@ -0,0 +1,86 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use futures::future::{BoxFuture, Shared};
+
+use crate::{handlers::LoginType, login::ProfileSelection, outcome::LoginReport};
+
+/// What a login is for. Only one login runs per session or profile, as they
+/// would all share its token cache.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct LoginTarget {
+    pub(crate) login_type: LoginType,
+    pub(crate) name: String,
+    pub(crate) selection: ProfileSelection,
+}
+
+/// The result of a running login, which every request joining it awaits.
+pub(crate) type SharedLogin = Shared<BoxFuture<'static, Result<LoginReport, String>>>;
+
+struct ActiveLogin {
+    id: u64,
+    target: LoginTarget,
+    cancelled: Arc<AtomicBool>,
+    result: SharedLogin,
+}
+
+/// The logins in progress, so a second request for the same session or profile
+/// joins the running login instead of starting another device flow.
+#[derive(Default)]
+pub(crate) struct LoginRegistry {
+    next_id: u64,
+    active: Vec<ActiveLogin>,
+}
+
+impl LoginRegistry {
+    /// The ID, target and result of the login in progress for the session or
+    /// profile of `target`, whichever profiles it selects.
+    pub(crate) fn find(&self, target: &LoginTarget) -> Option<(u64, &LoginTarget, SharedLogin)> {
+        self.active
+            .iter()
+            .find(|login| {
+                login.target.login_type == target.login_type && login.target.name == target.name
+            })
+            .map(|login| (login.id, &login.target, login.result.clone()))
+    }
+
+    /// Registers a new login for `target`. `start` gets the login's ID and the
+    /// flag `cancel` sets, and returns the running login.
+    pub(crate) fn start(
+        &mut self,
+        target: LoginTarget,
+        start: impl FnOnce(u64, Arc<AtomicBool>) -> SharedLogin,
+    ) -> SharedLogin {
+        self.next_id += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let result = start(self.next_id, cancelled.clone());
+        self.active.push(ActiveLogin {
+            id: self.next_id,
+            target,
+            cancelled,
+            result: result.clone(),
+        });
+        result
+    }
+
+    /// Forgets a login once it's over.
+    pub(crate) fn finish(&mut self, id: u64) {
+        self.active.retain(|login| login.id != id);
+    }
+
+    /// Asks the login for a session or profile to stop. Returns how many were
+    /// running.
+    pub(crate) fn cancel(&self, login_type: &LoginType, name: &str) -> usize {
+        let matching = self
+            .active
+            .iter()
+            .filter(|login| login.target.login_type == *login_type && login.target.name == name)
+            .collect::<Vec<_>>();
+        for login in &matching {
+            login.cancelled.store(true, Ordering::SeqCst);
+        }
+        matching.len()
+    }
+}
@@ -1,22 +1,39 @@
 use std::path::PathBuf;
 
-use aws::config::AwsConfigSections;
+use aws::{
+    config::AwsConfigSections,
+    paths::{is_watched_path, sso_cache_dir, watched_dirs},
+};
 use futures::{
     SinkExt, StreamExt,
     channel::mpsc::{Receiver, channel},
 };
 use global::trace_err_ret;
 use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
-use tauri::{AppHandle, Emitter, Manager, async_runtime::spawn};
+use tauri::{AppHandle, Emitter, Manager, WindowEvent, async_runtime::spawn};
 use tokio::sync::Mutex;
 use tracing::{Level, info};
 use utils::fetch_profiles_new;
 
+mod authcode;
 mod aws;
 mod cache;
-mod error;
+mod diagnostics;
+mod discovery;
+mod editor;
+mod generator;
 mod global;
 mod handlers;
+mod inflight;
+mod login;
+mod notifications;
+mod outcome;
+mod provider;
+mod registration;
+mod scheduler;
+mod settings;
+mod terminal;
+mod tray;
 mod utils;
 
 fn setup_logging() {
@@ -64,15 +81,18 @@ fn async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Resul
     Ok((watcher, rx))
 }
 
-async fn async_watch(path: PathBuf, app: AppHandle) -> notify::Result<()> {
+async fn async_watch(paths: Vec<PathBuf>, app: AppHandle) -> notify::Result<()> {
     let (mut watcher, mut rx) = async_watcher()?;
 
-    // Add a path to be watched. All files and directories at that path and
-    // below will be monitored for changes.
-    watcher.watch(path.as_ref(), RecursiveMode::Recursive)?;
+    // Watch the directories holding the resolved files, rather than the files
+    // themselves, so atomic replacements of the files are picked up as well.
+    for path in &paths {
+        watcher.watch(path.as_ref(), RecursiveMode::NonRecursive)?;
+    }
 
     while let Some(res) = rx.next().await {
         match res {
+            Ok(event) if !event.paths.iter().any(|p| is_watched_path(p)) => {}
             Ok(event) => match event.kind {
                 notify::EventKind::Create(_)
                 | notify::EventKind::Modify(_)
@@ -80,6 +100,7 @@ async fn async_watch(path: PathBuf, app: AppHandle) -> notify::Result<()> {
                     if let Err(e) = app.emit_to("main", "configs-change", "change") {
                         tracing::error!("emit error: {:?}", e);
                     }
+                    tray::update_tray(&app);
                 }
                 _ => {}
             },
@@ -92,6 +113,8 @@ async fn async_watch(path: PathBuf, app: AppHandle) -> notify::Result<()> {
 
 pub(crate) struct ButlerState {
     pub(crate) aws_profiles: AwsConfigSections,
+    pub(crate) logins: inflight::LoginRegistry,
+    pub(crate) settings: settings::Settings,
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -99,28 +122,73 @@ pub async fn run() -> Result<(), anyhow::Error> {
     setup_logging();
     info!("Logging initialized");
 
-    let home_dir = dirs::home_dir().ok_or_else(|| trace_err_ret("home directory not found..."))?;
-    let path = home_dir.join(".aws");
+    // make sure the sso cache exists so logins made after startup are watched
+    std::fs::create_dir_all(sso_cache_dir()?)?;
+    let paths = watched_dirs()?;
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .setup(|app| {
+            tray::create_tray(app.handle())?;
             spawn(setup(app.handle().clone()));
-            spawn(async_watch(path, app.handle().clone()));
+            spawn(async_watch(paths, app.handle().clone()));
+            spawn(scheduler::run_auto_refresh(app.handle().clone()));
+            spawn(notifications::run_expiry_notifications(
+                app.handle().clone(),
+            ));
             Ok(())
         })
+        // closing the main window keeps butler running in the tray, Quit in
+        // the tray menu exits
+        .on_window_event(|window, event| {
+            if window.label() == "main"
+                && let WindowEvent::CloseRequested { api, .. } = event
+            {
+                api.prevent_close();
+                if let Err(e) = window.hide() {
+                    tracing::error!("unable to hide the main window: {:?}", e);
+                }
+            }
+        })
         .manage(Mutex::new(ButlerState {
             aws_profiles: fetch_profiles_new()?,
+            logins: Default::default(),
+            settings: settings::load_settings_or_default(),
         }))
         .invoke_handler(tauri::generate_handler![
             handlers::authenticate_aws,
+            handlers::authenticate_session_profiles,
+            handlers::cancel_login,
             handlers::refresh_profiles,
             handlers::fetch_butler_config,
+            handlers::logout,
+            handlers::discover_accounts,
+            handlers::preview_generated_profiles,
+            handlers::generate_profiles,
+            handlers::get_config_section,
+            handlers::create_config_section,
+            handlers::update_config_section,
+            handlers::rename_config_section,
+            handlers::delete_config_section,
+            handlers::diagnose_config,
+            handlers::load_settings,
+            handlers::save_settings,
         ])
         // NOTE: This error is fine
         .run(tauri::generate_context!())
         .map_err(Into::into)
 }
 
+/// Entry point of the `credential_process` binary, prints the credentials for
+/// `profile_name` as the Version 1 JSON document SDKs expect.
+pub async fn credential_process(profile_name: &str) -> Result<(), anyhow::Error> {
+    provider::print_process_credentials(profile_name).await
+}
+
+/// Entry point of the command line binary.
+pub async fn cli() -> Result<(), anyhow::Error> {
+    terminal::run_cli().await
+}
+
 async fn setup(app: AppHandle) -> Result<(), anyhow::Error> {
     let main_win = app
         .get_webview_window("main")
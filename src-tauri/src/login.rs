@@ -0,0 +1,1150 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use aws_config::Region;
+use aws_sdk_ssooidc::{
+    error::ProvideErrorMetadata,
+    operation::{
+        create_token::CreateTokenOutput, start_device_authorization::StartDeviceAuthorizationOutput,
+    },
+};
+use futures::{StreamExt, stream::FuturesUnordered};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    authcode::AuthCodeLogin,
+    aws::{
+        config::{AwsConfigSections, Profile},
+        credentials::{
+            RoleCredentials, remove_credentials_for_profiles, store_credentials_for_profile,
+        },
+    },
+    cache::{get_login_from_cache, remove_token_from_cache, store_token_in_cache},
+    diagnostics::ensure_loginable,
+    editor::SectionType,
+    outcome::{LoginReport, ProfileOutcome, classify_error, is_token_rejected},
+    provider::remove_cached_credentials,
+    registration::{ClientRegistration, get_or_register_client},
+    settings::Settings,
+    trace_err_ret,
+    utils::matches_wildcard,
+};
+
+/// STS is global, chained profiles without a `region` use this endpoint.
+const DEFAULT_STS_REGION: &str = "us-east-1";
+
+/// Cached SSO tokens with less time left than this aren't reused for a login,
+/// as the token would expire right after.
+const MIN_TOKEN_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+/// Polling interval when the device authorization doesn't name one.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Added to the polling interval on every `slow_down` response, see RFC 8628.
+const SLOW_DOWN_INCREMENT: Duration = Duration::from_secs(5);
+
+/// How long a device code is valid when the authorization doesn't say.
+const DEFAULT_DEVICE_CODE_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+/// Caps how long a login waits for approval, in minutes. Set on an sso-session
+/// or a legacy profile, without it a login waits until the device code expires.
+const LOGIN_TIMEOUT_KEY: &str = "butler_login_timeout_minutes";
+
+/// Picks the login flow, `authorization-code` or `device-code`. Set on an
+/// sso-session or a legacy profile, without it logins use the device flow.
+const LOGIN_FLOW_KEY: &str = "butler_login_flow";
+
+/// Where the app opens the page approving a login: `embedded`, `default` for
+/// the system browser, a browser name such as `firefox`, or a command run with
+/// the URL appended. Set on an sso-session or a legacy profile.
+const LOGIN_BROWSER_KEY: &str = "butler_login_browser";
+
+/// Sets the login browser for every login without `butler_login_browser`.
+const LOGIN_BROWSER_ENV: &str = "AWTH_BUTLER_LOGIN_BROWSER";
+
+/// Role credentials fetched for SSO profiles, by profile name.
+type FetchedCredentials<'a> = Vec<(&'a str, Result<RoleCredentials, anyhow::Error>)>;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct SsoToken {
+    pub(crate) access_token: String,
+    pub(crate) refresh_token: Option<String>,
+    pub(crate) expiration: SystemTime,
+}
+
+/// Which profiles of a session a login fetches credentials for.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub(crate) enum ProfileSelection {
+    /// Every profile using the session.
+    #[default]
+    All,
+    /// Only the profiles with these names.
+    Names(Vec<String>),
+    /// Profiles listing this tag in their `butler_tags`.
+    Tag(String),
+    /// Profiles whose name matches a pattern like `prod-*`, see
+    /// `matches_wildcard`.
+    Filter(String),
+}
+
+impl ProfileSelection {
+    fn matches(&self, profile: &Profile) -> bool {
+        match self {
+            ProfileSelection::All => true,
+            ProfileSelection::Names(names) => names.iter().any(|name| name == profile.name()),
+            ProfileSelection::Tag(tag) => profile.tags().any(|t| t == tag),
+            ProfileSelection::Filter(pattern) => matches_wildcard(pattern, profile.name()),
+        }
+    }
+}
+
+/// A step of a login, reported as it happens so long logins don't look stuck.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) enum LoginStep {
+    /// A cached token was still valid, no approval is needed.
+    TokenReused,
+    /// The OIDC client to authorize is registered, or was already.
+    ClientRegistered,
+    /// The authorization the user has to approve was created. Only the
+    /// device flow has a code to confirm.
+    AuthorizationStarted {
+        user_code: Option<String>,
+        verification_uri: String,
+    },
+    /// The authorization was shown and is waiting for the user.
+    WaitingForApproval,
+    /// A new token was received, by approval or by refresh.
+    TokenReceived,
+    /// Credentials for a profile were fetched or its role assumed.
+    CredentialsFetched { profile_name: String, success: bool },
+    /// A profile's credentials were written to the credentials file.
+    CredentialsWritten { profile_name: String },
+    /// The login is over, with how many profiles succeeded and failed.
+    Done { succeeded: usize, failed: usize },
+    /// The login itself failed, no profiles were attempted.
+    Failed { error: String },
+}
+
+/// How a front end presents the device authorization to the user.
+pub(crate) trait AuthPrompt {
+    /// Shows the user where to approve the login, and the code to confirm
+    /// there for the device flow.
+    fn open(
+        &mut self,
+        verification_uri: &str,
+        user_code: Option<&str>,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Errors once the user has abandoned the login.
+    fn check_cancelled(&mut self) -> Result<(), anyhow::Error>;
+
+    /// Cleans up once the login is over, whether it succeeded or not.
+    fn close(&mut self) -> Result<(), anyhow::Error>;
+
+    /// Reports a step of the login, front ends that don't show progress
+    /// ignore it.
+    fn progress(&mut self, _step: LoginStep) {}
+
+    /// Whether someone is there to approve a login. Without them only a
+    /// cached or refreshable token is used, before any client is registered.
+    fn interactive(&self) -> bool {
+        true
+    }
+}
+
+pub(crate) async fn generate_aws_config(region: Region) -> aws_config::SdkConfig {
+    aws_config::defaults(aws_config::BehaviorVersion::latest())
+        .region(region)
+        .load()
+        .await
+}
+
+async fn run_client_authorization(
+    sso_oidc_client: &aws_sdk_ssooidc::Client,
+    registration: &ClientRegistration,
+    start_url: &str,
+) -> Result<StartDeviceAuthorizationOutput, anyhow::Error> {
+    Ok(sso_oidc_client
+        .start_device_authorization()
+        .client_id(&registration.client_id)
+        .client_secret(&registration.client_secret)
+        .start_url(start_url)
+        .send()
+        .await?)
+}
+
+/// How the user approves a login.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum LoginFlow {
+    /// The device authorization grant, approved by confirming a code. Works
+    /// with a browser on another machine.
+    #[default]
+    DeviceCode,
+    /// The authorization code grant with PKCE, redirecting back to a loopback
+    /// listener. Needs no code, but the browser has to run on this machine.
+    AuthorizationCode,
+}
+
+/// The login settings of an sso-session or legacy profile.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct LoginSettings {
+    pub(crate) flow: LoginFlow,
+    pub(crate) timeout: Option<Duration>,
+}
+
+/// A setting of a login's sso-session or, for legacy profiles, of the
+/// profile itself.
+fn login_setting<'a>(
+    profile_set: &'a AwsConfigSections,
+    profile: Option<&'a Profile>,
+    session_name: Option<&str>,
+    key: &str,
+) -> Option<&'a str> {
+    match session_name {
+        Some(session_name) => profile_set.sessions.get(session_name)?.get(key),
+        None => profile?.get(key),
+    }
+}
+
+/// Reads `butler_login_flow` and `butler_login_timeout_minutes` for a login,
+/// falling back to the app settings. Invalid values are logged and ignored.
+pub(crate) fn login_settings(
+    profile_set: &AwsConfigSections,
+    settings: &Settings,
+    profile: Option<&Profile>,
+    session_name: Option<&str>,
+) -> LoginSettings {
+    let profile_name = profile.map(|prof| prof.name());
+    let flow = match login_setting(profile_set, profile, session_name, LOGIN_FLOW_KEY) {
+        Some("device-code") => Some(LoginFlow::DeviceCode),
+        Some("authorization-code") => Some(LoginFlow::AuthorizationCode),
+        Some(other) => {
+            tracing::warn!("ignoring invalid {}: {}", LOGIN_FLOW_KEY, other);
+            None
+        }
+        None => None,
+    }
+    .or_else(|| settings.lookup(profile_name, session_name, |section| section.login_flow))
+    .unwrap_or_default();
+    let timeout = login_setting(profile_set, profile, session_name, LOGIN_TIMEOUT_KEY)
+        .and_then(|setting| match setting.parse::<u64>() {
+            Ok(minutes) if minutes > 0 => Some(minutes),
+            _ => {
+                tracing::warn!("ignoring invalid {}: {}", LOGIN_TIMEOUT_KEY, setting);
+                None
+            }
+        })
+        .or_else(|| {
+            settings.lookup(profile_name, session_name, |section| {
+                section.login_timeout_minutes
+            })
+        })
+        .map(|minutes| Duration::from_secs(minutes * 60));
+    LoginSettings { flow, timeout }
+}
+
+/// Where the app shows the page approving a login.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) enum LoginBrowser {
+    /// A window of the app, which cancels the login when closed.
+    #[default]
+    Embedded,
+    /// A browser found by name, `Browser::Default` being the system's one.
+    /// Others can only be opened by name on macOS.
+    Named(webbrowser::Browser),
+    /// A program and its arguments, called with the URL appended, e.g. to pick
+    /// a browser profile.
+    Command(Vec<String>),
+}
+
+impl FromStr for LoginBrowser {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(trace_err_ret("Login browser is empty!"));
+        }
+        Ok(match s {
+            "embedded" => LoginBrowser::Embedded,
+            "system" => LoginBrowser::Named(webbrowser::Browser::Default),
+            _ => match webbrowser::Browser::from_str(&s.to_lowercase()) {
+                Ok(browser)
+                    if cfg!(target_os = "macos")
+                        || matches!(browser, webbrowser::Browser::Default) =>
+                {
+                    LoginBrowser::Named(browser)
+                }
+                // elsewhere webbrowser only knows the default browser, a name
+                // like firefox is run as a command instead
+                _ => LoginBrowser::Command(s.split_whitespace().map(String::from).collect()),
+            },
+        })
+    }
+}
+
+/// Reads `butler_login_browser` for a login, falling back to the app settings
+/// and then `AWTH_BUTLER_LOGIN_BROWSER`. Invalid values are logged and ignored.
+pub(crate) fn login_browser(
+    profile_set: &AwsConfigSections,
+    settings: &Settings,
+    profile: Option<&Profile>,
+    session_name: Option<&str>,
+) -> LoginBrowser {
+    let setting = login_setting(profile_set, profile, session_name, LOGIN_BROWSER_KEY)
+        .map(|s| s.to_string())
+        .or_else(|| {
+            settings.lookup(profile.map(|prof| prof.name()), session_name, |section| {
+                section.login_browser.clone()
+            })
+        })
+        .or_else(|| std::env::var(LOGIN_BROWSER_ENV).ok());
+    match setting.as_deref().map(LoginBrowser::from_str) {
+        None => LoginBrowser::Embedded,
+        Some(Ok(browser)) => browser,
+        Some(Err(e)) => {
+            tracing::warn!("ignoring invalid {}: {:?}", LOGIN_BROWSER_KEY, e);
+            LoginBrowser::Embedded
+        }
+    }
+}
+
+/// The token in a successful `CreateToken` response.
+pub(crate) fn token_from_output(output: &CreateTokenOutput) -> Result<SsoToken, anyhow::Error> {
+    Ok(SsoToken {
+        access_token: output
+            .access_token()
+            .ok_or_else(|| trace_err_ret("Access token missing from completed auth!"))?
+            .to_string(),
+        refresh_token: output.refresh_token().map(|s| s.to_string()),
+        expiration: SystemTime::now() + Duration::from_secs(output.expires_in() as u64),
+    })
+}
+
+/// Sleeps for `duration`, checking every second whether the user abandoned
+/// the login so a closed prompt doesn't wait out a long polling interval.
+async fn wait_cancellable(
+    duration: Duration,
+    prompt: &mut (dyn AuthPrompt + Send),
+) -> Result<(), anyhow::Error> {
+    let until = tokio::time::Instant::now() + duration;
+    loop {
+        prompt.check_cancelled()?;
+        let now = tokio::time::Instant::now();
+        if now >= until {
+            return Ok(());
+        }
+        tokio::time::sleep((until - now).min(Duration::from_secs(1))).await;
+    }
+}
+
+/// Polls for the token of a device authorization as RFC 8628 describes: at
+/// the given interval, slowing down when asked to, until the device code
+/// expires or `timeout` passes. Pending approvals keep the polling going,
+/// a denied or expired authorization ends it straight away.
+async fn poll_for_token(
+    auth_out: &StartDeviceAuthorizationOutput,
+    sso_oidc_client: &aws_sdk_ssooidc::Client,
+    registration: &ClientRegistration,
+    timeout: Option<Duration>,
+    prompt: &mut (dyn AuthPrompt + Send),
+) -> Result<SsoToken, anyhow::Error> {
+    let device_code = auth_out
+        .device_code()
+        .ok_or_else(|| trace_err_ret("Device code not found!"))?;
+    let mut interval = match auth_out.interval() {
+        secs if secs > 0 => Duration::from_secs(secs as u64),
+        _ => DEFAULT_POLL_INTERVAL,
+    };
+    let lifetime = match auth_out.expires_in() {
+        secs if secs > 0 => Duration::from_secs(secs as u64),
+        _ => DEFAULT_DEVICE_CODE_LIFETIME,
+    };
+    let wait = timeout.map_or(lifetime, |timeout| timeout.min(lifetime));
+    let deadline = tokio::time::Instant::now() + wait;
+
+    loop {
+        prompt.check_cancelled()?;
+        match sso_oidc_client
+            .create_token()
+            .client_id(&registration.client_id)
+            .client_secret(&registration.client_secret)
+            .grant_type("urn:ietf:params:oauth:grant-type:device_code")
+            .device_code(device_code)
+            .send()
+            .await
+        {
+            Ok(output) => return token_from_output(&output),
+            Err(err) => match err.as_service_error() {
+                Some(e) if e.is_authorization_pending_exception() => {}
+                Some(e) if e.is_slow_down_exception() => {
+                    interval += SLOW_DOWN_INCREMENT;
+                    tracing::debug!("asked to slow down, polling every {:?}", interval);
+                }
+                Some(e) if e.is_access_denied_exception() => {
+                    return Err(trace_err_ret("The login was denied in the browser!"));
+                }
+                Some(e) if e.is_expired_token_exception() => {
+                    return Err(trace_err_ret(
+                        "The login expired before it was approved, start it again!",
+                    ));
+                }
+                Some(e) => {
+                    return Err(trace_err_ret(&format!(
+                        "SSO rejected the login with {}: {}",
+                        e.code().unwrap_or("an unknown error"),
+                        e.message().unwrap_or("no details given")
+                    )));
+                }
+                // network trouble, the next poll may well get through
+                None => tracing::warn!("polling for the sso token failed: {:?}", err),
+            },
+        }
+
+        if tokio::time::Instant::now() + interval >= deadline {
+            return Err(trace_err_ret(&format!(
+                "Timed out after {} minutes waiting for the login to be approved!",
+                wait.as_secs().div_ceil(60)
+            )));
+        }
+        wait_cancellable(interval, prompt).await?;
+    }
+}
+
+async fn execute_login_flow(
+    auth_out: &StartDeviceAuthorizationOutput,
+    sso_oidc_client: &aws_sdk_ssooidc::Client,
+    registration: &ClientRegistration,
+    timeout: Option<Duration>,
+    prompt: &mut (dyn AuthPrompt + Send),
+) -> Result<SsoToken, anyhow::Error> {
+    // the login may have been called off while the client was registered
+    prompt.check_cancelled()?;
+    let verification_uri = auth_out
+        .verification_uri_complete()
+        .ok_or_else(|| trace_err_ret("No verification uri after client registration!"))?;
+    let user_code = auth_out
+        .user_code()
+        .ok_or_else(|| trace_err_ret("No user code after client registration!"))?;
+    prompt.progress(LoginStep::AuthorizationStarted {
+        user_code: Some(user_code.to_string()),
+        verification_uri: verification_uri.to_string(),
+    });
+    prompt.open(verification_uri, Some(user_code))?;
+    prompt.progress(LoginStep::WaitingForApproval);
+
+    let token = poll_for_token(auth_out, sso_oidc_client, registration, timeout, prompt).await;
+    if let Err(e) = prompt.close() {
+        tracing::warn!("unable to close the login prompt: {:?}", e);
+    }
+    token
+}
+
+/// Fetches role credentials for an SSO profile using an SSO access token.
+pub(crate) async fn fetch_role_credentials(
+    sso_client: &aws_sdk_sso::Client,
+    profile: &Profile,
+    access_token: &str,
+) -> Result<RoleCredentials, anyhow::Error> {
+    sso_client
+        .get_role_credentials()
+        .account_id(
+            profile
+                .sso_account_id()
+                .ok_or_else(|| trace_err_ret("No account ID found for profile!"))?,
+        )
+        .role_name(
+            profile
+                .sso_role_name()
+                .ok_or_else(|| trace_err_ret("No role name found for profile!"))?,
+        )
+        .access_token(access_token)
+        .send()
+        .await?
+        .try_into()
+}
+
+/// Assumes the role of a chained profile with its source profile's credentials.
+async fn assume_role(
+    profile: &Profile,
+    source: &RoleCredentials,
+) -> Result<RoleCredentials, anyhow::Error> {
+    let role_arn = profile
+        .role_arn()
+        .ok_or_else(|| trace_err_ret("No role ARN found for profile!"))?;
+    let duration_seconds = profile
+        .duration_seconds()
+        .map(|secs| secs.parse::<i32>())
+        .transpose()
+        .map_err(|_| trace_err_ret("Invalid duration_seconds for profile!"))?;
+    let role_session_name = profile
+        .role_session_name()
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| format!("awth-butler-{}", chrono::Utc::now().timestamp()));
+
+    let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+        .region(Region::new(
+            profile.region().unwrap_or(DEFAULT_STS_REGION).to_string(),
+        ))
+        .credentials_provider(aws_sdk_sts::config::Credentials::new(
+            &source.access_key_id,
+            &source.secret_access_key,
+            Some(source.session_token.clone()),
+            Some(source.expiration.into()),
+            "awth-butler",
+        ))
+        .load()
+        .await;
+    let output = aws_sdk_sts::Client::new(&config)
+        .assume_role()
+        .role_arn(role_arn)
+        .role_session_name(role_session_name)
+        .set_external_id(profile.external_id().map(|id| id.to_string()))
+        .set_duration_seconds(duration_seconds)
+        .send()
+        .await?;
+    output
+        .credentials()
+        .ok_or_else(|| trace_err_ret("Missing credentials after assuming role!"))?
+        .try_into()
+}
+
+/// Writes credentials that were fetched and records the outcome either way.
+/// Profiles that got credentials are added to `resolved` for their chains.
+fn record_credentials<'a>(
+    profile_name: &'a str,
+    creds: Result<RoleCredentials, ProfileOutcome>,
+    resolved: &mut HashMap<&'a str, RoleCredentials>,
+    report: &mut LoginReport,
+    prompt: &mut (dyn AuthPrompt + Send),
+) {
+    let outcome = match creds {
+        Ok(creds) => match store_credentials_for_profile(profile_name, &creds) {
+            Ok(()) => {
+                prompt.progress(LoginStep::CredentialsWritten {
+                    profile_name: profile_name.to_string(),
+                });
+                resolved.insert(profile_name, creds);
+                ProfileOutcome::Success
+            }
+            Err(e) => ProfileOutcome::Failed(e.to_string()),
+        },
+        Err(outcome) => outcome,
+    };
+    if !outcome.is_success() {
+        tracing::error!("login failed for profile {}: {:?}", profile_name, outcome);
+    }
+    report.push(profile_name, outcome);
+}
+
+/// Assumes the roles of the `chained` profiles, sources first, and writes
+/// their credentials too. A failed profile doesn't stop the other chains,
+/// only the profiles built on top of it.
+async fn assume_chained_roles<'a>(
+    chained: Vec<&'a Profile>,
+    mut resolved: HashMap<&'a str, RoleCredentials>,
+    report: &mut LoginReport,
+    prompt: &mut (dyn AuthPrompt + Send),
+) {
+    for prof in chained {
+        let source_name = prof.source_profile().unwrap_or_default();
+        let creds = match resolved.get(source_name) {
+            Some(source) => {
+                let creds = assume_role(prof, source).await;
+                prompt.progress(LoginStep::CredentialsFetched {
+                    profile_name: prof.name().to_string(),
+                    success: creds.is_ok(),
+                });
+                creds.map_err(|e| classify_error(&e))
+            }
+            None => Err(ProfileOutcome::SourceFailed(format!(
+                "source profile {} has no credentials",
+                source_name
+            ))),
+        };
+        record_credentials(prof.name(), creds, &mut resolved, report, prompt);
+    }
+}
+
+/// Attempts a `refresh_token` grant using the registration and refresh token
+/// cached from a previous login. Returns `None` whenever the cached entry is
+/// unusable or the refresh is rejected, so the caller can fall back to the
+/// interactive device flow.
+pub(crate) async fn refresh_cached_token(
+    sso_oidc_client: &aws_sdk_ssooidc::Client,
+    session_name: Option<&str>,
+    sso_start_url: &str,
+) -> Option<(SsoToken, ClientRegistration)> {
+    let cached = match get_login_from_cache(session_name, sso_start_url) {
+        Ok(Some(cached)) if cached.is_refreshable() => cached,
+        Ok(_) => return None,
+        Err(e) => {
+            tracing::warn!("unable to read cached login: {:?}", e);
+            return None;
+        }
+    };
+    let registration = cached.registration?;
+    let refresh_token = cached.token.refresh_token?;
+
+    let output = match sso_oidc_client
+        .create_token()
+        .client_id(&registration.client_id)
+        .client_secret(&registration.client_secret)
+        .grant_type("refresh_token")
+        .refresh_token(&refresh_token)
+        .send()
+        .await
+    {
+        Ok(output) => output,
+        Err(e) => {
+            tracing::warn!(
+                "refresh token rejected, falling back to device login: {:?}",
+                e
+            );
+            return None;
+        }
+    };
+
+    let token = SsoToken {
+        access_token: output.access_token()?.to_string(),
+        // the refresh token is not always rotated, keep the old one if so
+        refresh_token: output
+            .refresh_token()
+            .map(|s| s.to_string())
+            .or(Some(refresh_token)),
+        expiration: SystemTime::now() + Duration::from_secs(output.expires_in() as u64),
+    };
+    tracing::info!("silently refreshed sso token for {}", sso_start_url);
+    Some((token, registration))
+}
+
+/// Gets a fresh SSO token, silently if a cached refresh token allows it,
+/// otherwise through the login flow in `settings`, shown by `prompt`. The
+/// device flow stands in when the authorization code flow can't be set up.
+async fn obtain_sso_token(
+    sso_oidc_client: &aws_sdk_ssooidc::Client,
+    session_name: Option<&str>,
+    sso_region: &str,
+    sso_start_url: &str,
+    settings: LoginSettings,
+    prompt: &mut (dyn AuthPrompt + Send),
+) -> Result<(SsoToken, ClientRegistration), anyhow::Error> {
+    if let Some(refreshed) =
+        refresh_cached_token(sso_oidc_client, session_name, sso_start_url).await
+    {
+        return Ok(refreshed);
+    }
+    if !prompt.interactive() {
+        return Err(trace_err_ret(
+            "Login required, the SSO session has expired and can't be refreshed!",
+        ));
+    }
+
+    if settings.flow == LoginFlow::AuthorizationCode {
+        match AuthCodeLogin::prepare(sso_oidc_client, sso_region, sso_start_url).await {
+            Ok(login) => {
+                prompt.progress(LoginStep::ClientRegistered);
+                return login
+                    .run(sso_oidc_client, sso_region, settings.timeout, prompt)
+                    .await;
+            }
+            Err(e) => tracing::warn!(
+                "unable to set up the authorization code login, using the device flow: {:?}",
+                e
+            ),
+        }
+    }
+
+    let registration = get_or_register_client(
+        sso_oidc_client,
+        sso_region,
+        sso_start_url,
+        LoginFlow::DeviceCode,
+    )
+    .await?;
+    prompt.progress(LoginStep::ClientRegistered);
+    let response = run_client_authorization(sso_oidc_client, &registration, sso_start_url).await?;
+    let token = execute_login_flow(
+        &response,
+        sso_oidc_client,
+        &registration,
+        settings.timeout,
+        prompt,
+    )
+    .await?;
+    Ok((token, registration))
+}
+
+/// Picks the profiles of a session a login covers: the selected SSO profiles,
+/// the selected chained profiles and every profile a selected chain needs as
+/// its source. Returns the SSO profiles and the chained ones in the order
+/// their roles have to be assumed.
+fn select_session_profiles<'a>(
+    profile_set: &'a AwsConfigSections,
+    session_name: &str,
+    selection: &ProfileSelection,
+) -> Result<(Vec<&'a Profile>, Vec<&'a Profile>), anyhow::Error> {
+    let session_profiles = profile_set
+        .profiles
+        .values()
+        .filter(|prof| prof.sso_session() == Some(session_name))
+        .collect::<Vec<_>>();
+    let session_names = session_profiles
+        .iter()
+        .map(|prof| prof.name())
+        .collect::<Vec<_>>();
+    let session_chained = profile_set.chained_profiles(&session_names);
+
+    if let ProfileSelection::Names(names) = selection {
+        let unknown = names
+            .iter()
+            .filter(|name| {
+                !session_profiles
+                    .iter()
+                    .chain(&session_chained)
+                    .any(|prof| prof.name() == name.as_str())
+            })
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            return Err(trace_err_ret(&format!(
+                "Profiles {} don't log in through session {}!",
+                unknown.join(", "),
+                session_name
+            )));
+        }
+    }
+
+    // walk the chains from the top down so every selected profile pulls in
+    // the sources below it
+    let mut wanted = Vec::new();
+    for prof in session_chained.iter().rev() {
+        if selection.matches(prof) || wanted.contains(&prof.name()) {
+            wanted.push(prof.name());
+            wanted.extend(prof.source_profile());
+        }
+    }
+    let sources = session_profiles
+        .into_iter()
+        .filter(|prof| selection.matches(prof) || wanted.contains(&prof.name()))
+        .collect::<Vec<_>>();
+    let chained = session_chained
+        .into_iter()
+        .filter(|prof| wanted.contains(&prof.name()))
+        .collect::<Vec<_>>();
+
+    if sources.is_empty() && !matches!(selection, ProfileSelection::All) {
+        return Err(trace_err_ret(&format!(
+            "No profiles of session {} match the selection!",
+            session_name
+        )));
+    }
+    Ok((sources, chained))
+}
+
+/// The cached SSO token of a login, if it's valid for long enough to reuse.
+fn reusable_cached_token(session_name: Option<&str>, sso_start_url: &str) -> Option<SsoToken> {
+    match get_login_from_cache(session_name, sso_start_url) {
+        Ok(cached) => cached
+            .map(|cached| cached.token)
+            .filter(|tok| tok.expiration > SystemTime::now() + MIN_TOKEN_LIFETIME),
+        Err(e) => {
+            tracing::warn!("unable to read cached login: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Fetches role credentials for SSO profiles in parallel with one token,
+/// reporting each profile as its request comes back.
+async fn fetch_all_role_credentials<'a>(
+    sso_client: &aws_sdk_sso::Client,
+    profiles: &[&'a Profile],
+    access_token: &str,
+    prompt: &mut (dyn AuthPrompt + Send),
+) -> FetchedCredentials<'a> {
+    let mut tasks = profiles
+        .iter()
+        .map(|prof| async move {
+            let creds = fetch_role_credentials(sso_client, prof, access_token).await;
+            (prof.name(), creds)
+        })
+        .collect::<FuturesUnordered<_>>();
+    let mut fetched = Vec::with_capacity(profiles.len());
+    while let Some((profile_name, creds)) = tasks.next().await {
+        prompt.progress(LoginStep::CredentialsFetched {
+            profile_name: profile_name.to_string(),
+            success: creds.is_ok(),
+        });
+        fetched.push((profile_name, creds));
+    }
+    fetched
+}
+
+/// Fetches role credentials for `profiles` with the cached SSO token while it
+/// has enough time left. Only when there is no such token or SSO rejects it
+/// does this go through `obtain_sso_token`, so refreshing a profile doesn't
+/// take another trip through the identity provider.
+async fn fetch_with_sso_token<'a>(
+    config: &aws_config::SdkConfig,
+    session_name: Option<&str>,
+    sso_region: &str,
+    sso_start_url: &str,
+    profiles: &[&'a Profile],
+    settings: LoginSettings,
+    prompt: &mut (dyn AuthPrompt + Send),
+) -> Result<FetchedCredentials<'a>, anyhow::Error> {
+    let sso_client = aws_sdk_sso::Client::new(config);
+    if let Some(token) = reusable_cached_token(session_name, sso_start_url) {
+        prompt.progress(LoginStep::TokenReused);
+        let fetched =
+            fetch_all_role_credentials(&sso_client, profiles, &token.access_token, prompt).await;
+        if !fetched
+            .iter()
+            .any(|(_, creds)| creds.as_ref().is_err_and(is_token_rejected))
+        {
+            tracing::info!("reused cached sso token for {}", sso_start_url);
+            return Ok(fetched);
+        }
+        tracing::warn!(
+            "cached sso token for {} was rejected, logging in again",
+            sso_start_url
+        );
+    }
+
+    let (token, registration) = obtain_sso_token(
+        &aws_sdk_ssooidc::Client::new(config),
+        session_name,
+        sso_region,
+        sso_start_url,
+        settings,
+        prompt,
+    )
+    .await?;
+    prompt.progress(LoginStep::TokenReceived);
+    store_token_in_cache(
+        session_name,
+        sso_start_url,
+        sso_region,
+        &registration,
+        &token,
+    )?;
+    Ok(fetch_all_role_credentials(&sso_client, profiles, &token.access_token, prompt).await)
+}
+
+/// Logs into an sso-session and writes credentials for the profiles using it
+/// that `selection` picks, leaving the others as they are.
+pub(crate) async fn sso_session_login(
+    profile_set: &AwsConfigSections,
+    settings: &Settings,
+    session_name: &str,
+    selection: &ProfileSelection,
+    prompt: &mut (dyn AuthPrompt + Send),
+) -> Result<LoginReport, anyhow::Error> {
+    ensure_loginable(profile_set, SectionType::SsoSession, session_name)?;
+    // checked before the login so a typo doesn't cost a trip to the browser
+    let (profiles_using_session, chained) =
+        select_session_profiles(profile_set, session_name, selection)?;
+
+    // grab session information from config, if it exists
+    let session = profile_set
+        .sessions
+        .get(session_name)
+        .ok_or_else(|| trace_err_ret("Session not found!"))?;
+    let sso_region = session
+        .sso_region()
+        .ok_or_else(|| trace_err_ret("No region found for session!"))?;
+    let region = Region::new(sso_region.to_string());
+
+    let config = generate_aws_config(region).await;
+    let sso_start_url = session
+        .sso_start_url()
+        .ok_or_else(|| trace_err_ret("No start URL found for session!"))?;
+
+    let (fetchable, misconfigured): (Vec<&Profile>, Vec<&Profile>) = profiles_using_session
+        .into_iter()
+        .partition(|p| p.sso_account_id().is_some() && p.sso_role_name().is_some());
+
+    // reuse the cached token or refresh it, run the client authorization flow
+    // only if neither works
+    let fetched = fetch_with_sso_token(
+        &config,
+        Some(session_name),
+        sso_region,
+        sso_start_url,
+        &fetchable,
+        login_settings(profile_set, settings, None, Some(session_name)),
+        prompt,
+    )
+    .await?;
+
+    // every profile gets its own outcome, one failing doesn't stop the rest
+    // from being written. This is done sequentially as the writes share a file
+    let mut report = LoginReport::default();
+    let mut resolved = HashMap::new();
+    for prof in misconfigured {
+        let outcome = ProfileOutcome::Misconfigured(
+            "sso_account_id and sso_role_name are required".to_string(),
+        );
+        record_credentials(
+            prof.name(),
+            Err(outcome),
+            &mut resolved,
+            &mut report,
+            prompt,
+        );
+    }
+    for (prof_name, creds) in fetched {
+        let creds = creds.map_err(|e| classify_error(&e));
+        record_credentials(prof_name, creds, &mut resolved, &mut report, prompt);
+    }
+
+    // then build the role chains on top of the session profiles
+    assume_chained_roles(chained, resolved, &mut report, prompt).await;
+    Ok(report)
+}
+
+/// Logs in a single profile, either through its sso-session or, for legacy
+/// profiles, through the SSO settings on the profile itself. Chained profiles
+/// log in through the SSO profile their chain starts at, assuming only the
+/// roles on the way to the requested profile.
+pub(crate) async fn profile_login(
+    profile_set: &AwsConfigSections,
+    settings: &Settings,
+    profile_name: &str,
+    prompt: &mut (dyn AuthPrompt + Send),
+) -> Result<LoginReport, anyhow::Error> {
+    let requested = profile_set
+        .profiles
+        .get(profile_name)
+        .ok_or_else(|| trace_err_ret("Profile not found!"))?;
+    ensure_loginable(profile_set, SectionType::Profile, profile_name)?;
+    let prof = profile_set
+        .chain_root(requested)
+        .ok_or_else(|| trace_err_ret("Profile does not log in through SSO!"))?;
+    let session_name = prof.sso_session();
+    let (sso_region, sso_start_url) = profile_set
+        .sso_settings(prof)
+        .ok_or_else(|| trace_err_ret("No sso_region or sso_start_url found for profile!"))?;
+    let region = Region::new(sso_region.to_string());
+    let config = generate_aws_config(region).await;
+
+    let fetched = fetch_with_sso_token(
+        &config,
+        session_name,
+        sso_region,
+        sso_start_url,
+        &[prof],
+        login_settings(profile_set, settings, Some(prof), session_name),
+        prompt,
+    )
+    .await?;
+
+    let mut report = LoginReport::default();
+    let mut resolved = HashMap::new();
+    for (prof_name, creds) in fetched {
+        let creds = creds.map_err(|e| classify_error(&e));
+        record_credentials(prof_name, creds, &mut resolved, &mut report, prompt);
+    }
+    let chained = profile_set
+        .chain_path(requested)
+        .ok_or_else(|| trace_err_ret("Profile does not log in through SSO!"))?;
+    assume_chained_roles(chained, resolved, &mut report, prompt).await;
+    Ok(report)
+}
+
+/// Signs the cached access token out of AWS SSO and deletes its cache entries.
+/// The entries are removed even if the service rejects the logout, e.g. for a
+/// token that already expired.
+async fn revoke_cached_login(
+    session_name: Option<&str>,
+    sso_region: &str,
+    sso_start_url: &str,
+) -> Result<(), anyhow::Error> {
+    match get_login_from_cache(session_name, sso_start_url) {
+        Ok(Some(cached)) => {
+            let config = generate_aws_config(Region::new(sso_region.to_string())).await;
+            let sso_client = aws_sdk_sso::Client::new(&config);
+            if let Err(e) = sso_client
+                .logout()
+                .access_token(&cached.token.access_token)
+                .send()
+                .await
+            {
+                tracing::warn!("sso logout failed, removing cached token anyway: {:?}", e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => tracing::warn!("unable to read cached login: {:?}", e),
+    }
+    remove_token_from_cache(session_name, sso_start_url)
+}
+
+/// Removes every trace of role credentials butler wrote for these profiles
+/// and the profiles chained on top of them.
+fn remove_profile_credentials(
+    profile_set: &AwsConfigSections,
+    profile_names: &[&str],
+) -> Result<(), anyhow::Error> {
+    let mut profile_names = profile_names.to_vec();
+    profile_names.extend(
+        profile_set
+            .chained_profiles(&profile_names)
+            .into_iter()
+            .map(|prof| prof.name()),
+    );
+    remove_credentials_for_profiles(&profile_names)?;
+    for profile_name in &profile_names {
+        remove_cached_credentials(profile_name)?;
+    }
+    Ok(())
+}
+
+/// Logs out of an sso-session and removes the credentials of every profile
+/// using it.
+pub(crate) async fn sso_session_logout(
+    profile_set: &AwsConfigSections,
+    session_name: &str,
+) -> Result<(), anyhow::Error> {
+    let session = profile_set
+        .sessions
+        .get(session_name)
+        .ok_or_else(|| trace_err_ret("Session not found!"))?;
+    let sso_region = session
+        .sso_region()
+        .ok_or_else(|| trace_err_ret("No region found for session!"))?;
+    let sso_start_url = session
+        .sso_start_url()
+        .ok_or_else(|| trace_err_ret("No start URL found for session!"))?;
+    revoke_cached_login(Some(session_name), sso_region, sso_start_url).await?;
+
+    let profile_names = profile_set
+        .profiles
+        .values()
+        .filter(|prof| prof.sso_session() == Some(session_name))
+        .map(|prof| prof.name())
+        .collect::<Vec<_>>();
+    remove_profile_credentials(profile_set, &profile_names)
+}
+
+/// Logs out a single profile. Legacy profiles own their SSO login so it gets
+/// revoked too, while profiles of an sso-session only lose their credentials
+/// as the session is shared with other profiles.
+pub(crate) async fn profile_logout(
+    profile_set: &AwsConfigSections,
+    profile_name: &str,
+) -> Result<(), anyhow::Error> {
+    let prof = profile_set
+        .profiles
+        .get(profile_name)
+        .ok_or_else(|| trace_err_ret("Profile not found!"))?;
+    if prof.sso_session().is_none()
+        && let Some((sso_region, sso_start_url)) = profile_set.sso_settings(prof)
+    {
+        revoke_cached_login(None, sso_region, sso_start_url).await?;
+    }
+    remove_profile_credentials(profile_set, &[profile_name])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+[sso-session my-sso]
+sso_region = eu-west-1
+sso_start_url = https://my-org.awsapps.com/start
+
+[profile prod-admin]
+sso_session = my-sso
+sso_account_id = 111111111111
+sso_role_name = Admin
+butler_tags = prod, oncall
+
+[profile prod-readonly]
+sso_session = my-sso
+sso_account_id = 111111111111
+sso_role_name = ReadOnly
+butler_tags = prod
+
+[profile dev-admin]
+sso_session = my-sso
+sso_account_id = 222222222222
+sso_role_name = Admin
+
+[profile prod-deploy]
+role_arn = arn:aws:iam::111111111111:role/Deploy
+source_profile = prod-admin
+
+[profile other]
+sso_session = other-sso
+sso_account_id = 333333333333
+sso_role_name = Admin
+";
+
+    fn select(selection: ProfileSelection) -> Result<(Vec<String>, Vec<String>), anyhow::Error> {
+        let profile_set = AwsConfigSections::parse_str(CONFIG);
+        let (sources, chained) = select_session_profiles(&profile_set, "my-sso", &selection)?;
+        let mut sources = sources
+            .iter()
+            .map(|prof| prof.name().to_string())
+            .collect::<Vec<_>>();
+        sources.sort();
+        let chained = chained.iter().map(|prof| prof.name().to_string()).collect();
+        Ok((sources, chained))
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn all_selects_every_profile_of_the_session() {
+        assert_eq!(
+            select(ProfileSelection::All).unwrap(),
+            (
+                names(&["dev-admin", "prod-admin", "prod-readonly"]),
+                names(&["prod-deploy"])
+            )
+        );
+    }
+
+    #[test]
+    fn tags_select_the_profiles_listing_them() {
+        assert_eq!(
+            select(ProfileSelection::Tag("oncall".to_string())).unwrap(),
+            (names(&["prod-admin"]), names(&[]))
+        );
+        assert_eq!(
+            select(ProfileSelection::Tag("prod".to_string())).unwrap(),
+            (names(&["prod-admin", "prod-readonly"]), names(&[]))
+        );
+        assert!(select(ProfileSelection::Tag("missing".to_string())).is_err());
+    }
+
+    #[test]
+    fn filters_match_profile_names() {
+        assert_eq!(
+            select(ProfileSelection::Filter("*-admin".to_string())).unwrap(),
+            (names(&["dev-admin", "prod-admin"]), names(&[]))
+        );
+        assert!(select(ProfileSelection::Filter(String::new())).is_err());
+    }
+
+    #[test]
+    fn selected_chains_pull_in_their_sources() {
+        assert_eq!(
+            select(ProfileSelection::Names(names(&["prod-deploy"]))).unwrap(),
+            (names(&["prod-admin"]), names(&["prod-deploy"]))
+        );
+    }
+
+    #[test]
+    fn names_of_other_sessions_are_rejected() {
+        assert!(select(ProfileSelection::Names(names(&["dev-admin", "other"]))).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use tauri::{AppHandle, Manager};
+use tokio::sync::Mutex;
+
+use crate::{
+    ButlerState,
+    aws::config::AwsConfigSections,
+    handlers::{ButlerSsoConfig, LoginType, build_butler_config},
+    scheduler::auto_refresh_lead_time,
+    settings::Settings,
+    tray::start_login,
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+const SUMMARY: &str = "AWS login expiring";
+
+/// The action on a notification that starts the login.
+#[cfg(any(
+    target_os = "windows",
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+const LOGIN_ACTION: &str = "login";
+
+/// The soonest expiry of a session or legacy profile, what a login renews.
+struct Expiry {
+    login_type: LoginType,
+    name: String,
+    what: String,
+    expiration: DateTime<Utc>,
+}
+
+/// Profiles kept alive by auto refresh are left out, their credentials only
+/// run out along with the SSO token, which is warned about on its own.
+fn is_auto_refreshed(profile_set: &AwsConfigSections, settings: &Settings, name: &str) -> bool {
+    profile_set
+        .profiles
+        .get(name)
+        .is_some_and(|prof| auto_refresh_lead_time(profile_set, settings, prof).is_some())
+}
+
+/// Picks the soonest upcoming expiry for every session and legacy profile,
+/// from the SSO token and the credentials of the profiles it logs in.
+fn collect_expiries(
+    config: &ButlerSsoConfig,
+    profile_set: &AwsConfigSections,
+    settings: &Settings,
+) -> Vec<Expiry> {
+    let mut soonest: HashMap<(LoginType, String), Expiry> = HashMap::new();
+    let mut add = |login_type: LoginType, name: &str, what: String, expiration: DateTime<Utc>| {
+        let key = (login_type.clone(), name.to_string());
+        if soonest
+            .get(&key)
+            .is_none_or(|other| expiration < other.expiration)
+        {
+            soonest.insert(
+                key,
+                Expiry {
+                    login_type,
+                    name: name.to_string(),
+                    what,
+                    expiration,
+                },
+            );
+        }
+    };
+
+    for sess in config.sessions.iter().filter(|s| s.fresh) {
+        if let Some(exp) = sess.session_expiration {
+            add(
+                LoginType::SsoSession,
+                &sess.session_name,
+                format!("The SSO session {}", sess.session_name),
+                exp,
+            );
+        }
+    }
+    for prof in config.sso_profiles.iter().filter(|p| p.fresh) {
+        if let Some(exp) = prof.profile_expiration
+            && !is_auto_refreshed(profile_set, settings, &prof.profile_name)
+        {
+            add(
+                LoginType::SsoSession,
+                &prof.session_name,
+                format!("Credentials of profile {}", prof.profile_name),
+                exp,
+            );
+        }
+    }
+    for prof in config.legacy_profiles.iter().filter(|p| p.fresh) {
+        if let Some(exp) = prof.profile_expiration
+            && !is_auto_refreshed(profile_set, settings, &prof.profile_name)
+        {
+            add(
+                LoginType::LegacyProfile,
+                &prof.profile_name,
+                format!("Credentials of profile {}", prof.profile_name),
+                exp,
+            );
+        }
+    }
+    for prof in config.chained_profiles.iter().filter(|p| p.fresh) {
+        let Some(exp) = prof.profile_expiration else {
+            continue;
+        };
+        // chained profiles renew with the login of the SSO profile they start at
+        if let Some(root) = config
+            .sso_profiles
+            .iter()
+            .find(|p| p.profile_name == prof.root_profile)
+        {
+            add(
+                LoginType::SsoSession,
+                &root.session_name,
+                format!("Credentials of profile {}", prof.profile_name),
+                exp,
+            );
+        } else if config
+            .legacy_profiles
+            .iter()
+            .any(|p| p.profile_name == prof.root_profile)
+        {
+            add(
+                LoginType::LegacyProfile,
+                &prof.root_profile,
+                format!("Credentials of profile {}", prof.profile_name),
+                exp,
+            );
+        }
+    }
+    soonest.into_values().collect()
+}
+
+/// The smallest threshold the time left has fallen below, the larger ones
+/// aren't worth a notification of their own anymore.
+fn crossed_threshold(thresholds: &[u64], remaining: TimeDelta) -> Option<u64> {
+    thresholds
+        .iter()
+        .copied()
+        .filter(|minutes| remaining <= TimeDelta::minutes(*minutes as i64))
+        .min()
+}
+
+fn notify(app: &AppHandle, expiry: &Expiry, minutes_left: i64) -> Result<(), anyhow::Error> {
+    let body = format!(
+        "{} expires in {} minute{}.",
+        expiry.what,
+        minutes_left,
+        if minutes_left == 1 { "" } else { "s" }
+    );
+    let on_login = {
+        let app = app.clone();
+        let login_type = expiry.login_type.clone();
+        let name = expiry.name.clone();
+        move || start_login(&app, login_type, name)
+    };
+    show_notification(app, body, on_login)
+}
+
+/// Shows a notification with a Log In action through D-Bus.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn show_notification(
+    _app: &AppHandle,
+    body: String,
+    on_login: impl FnOnce() + Send + 'static,
+) -> Result<(), anyhow::Error> {
+    let handle = notify_rust::Notification::new()
+        .appname("Awth Butler")
+        .summary(SUMMARY)
+        .body(&body)
+        .action(LOGIN_ACTION, "Log In")
+        .show()?;
+    // blocks until the notification is acted on or dismissed
+    std::thread::spawn(move || {
+        handle.wait_for_action(|action| {
+            if action == LOGIN_ACTION {
+                on_login();
+            }
+        })
+    });
+    Ok(())
+}
+
+/// Shows a notification with a Log In button through the notification center,
+/// clicking the notification itself logs in as well.
+#[cfg(target_os = "macos")]
+fn show_notification(
+    _app: &AppHandle,
+    body: String,
+    on_login: impl FnOnce() + Send + 'static,
+) -> Result<(), anyhow::Error> {
+    use mac_notification_sys::{MainButton, Notification, NotificationResponse};
+
+    // sending blocks until the notification is acted on or dismissed
+    std::thread::spawn(move || {
+        match Notification::new()
+            .title(SUMMARY)
+            .message(&body)
+            .main_button(MainButton::SingleAction("Log In"))
+            .send()
+        {
+            Ok(NotificationResponse::ActionButton(_) | NotificationResponse::Click) => on_login(),
+            Ok(_) => {}
+            Err(e) => tracing::error!("unable to show a notification: {:?}", e),
+        }
+    });
+    Ok(())
+}
+
+/// Shows a toast with a Log In button, clicking the toast itself logs in as
+/// well. Only an installed app has its identifier registered for toasts.
+#[cfg(target_os = "windows")]
+fn show_notification(
+    app: &AppHandle,
+    body: String,
+    on_login: impl FnOnce() + Send + 'static,
+) -> Result<(), anyhow::Error> {
+    let mut on_login = Some(on_login);
+    tauri_winrt_notification::Toast::new(&app.config().identifier)
+        .title(SUMMARY)
+        .text1(&body)
+        .add_button("Log In", LOGIN_ACTION)
+        .on_activated(move |_action| {
+            if let Some(on_login) = on_login.take() {
+                on_login();
+            }
+            Ok(())
+        })
+        .show()?;
+    Ok(())
+}
+
+/// Logs the warning instead, there's no notification backend on mobile.
+#[cfg(any(target_os = "android", target_os = "ios"))]
+fn show_notification(
+    _app: &AppHandle,
+    body: String,
+    _on_login: impl FnOnce() + Send + 'static,
+) -> Result<(), anyhow::Error> {
+    tracing::warn!("{}: {}", SUMMARY, body);
+    Ok(())
+}
+
+/// Warns about sessions and credentials about to expire, once for every
+/// threshold in the notification settings an expiry gets close to.
+pub(crate) async fn run_expiry_notifications(app: AppHandle) {
+    #[cfg(target_os = "macos")]
+    if let Err(e) = mac_notification_sys::set_application(&app.config().identifier) {
+        tracing::warn!("unable to set the notification sender: {:?}", e);
+    }
+
+    // the threshold last notified by target, for the expiry it was about
+    let mut notified: HashMap<(LoginType, String), (DateTime<Utc>, u64)> = HashMap::new();
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        ticker.tick().await;
+
+        let (expiries, thresholds) = {
+            let state = app.state::<Mutex<ButlerState>>();
+            let state = state.lock().await;
+            if !state.settings.notifications.enabled {
+                continue;
+            }
+            let config = match build_butler_config(&state.aws_profiles, &state.settings) {
+                Ok(config) => config,
+                Err(e) => {
+                    tracing::error!("unable to check for expiring logins: {:?}", e);
+                    continue;
+                }
+            };
+            (
+                collect_expiries(&config, &state.aws_profiles, &state.settings),
+                state.settings.notifications.thresholds_minutes.clone(),
+            )
+        };
+
+        let now = Utc::now();
+        for expiry in expiries {
+            let remaining = expiry.expiration - now;
+            if remaining <= TimeDelta::zero() {
+                continue;
+            }
+            let Some(threshold) = crossed_threshold(&thresholds, remaining) else {
+                continue;
+            };
+            let key = (expiry.login_type.clone(), expiry.name.clone());
+            if notified
+                .get(&key)
+                .is_some_and(|(exp, last)| *exp == expiry.expiration && *last <= threshold)
+            {
+                continue;
+            }
+            // rounded up, so a warning never claims less time than is left
+            let minutes_left = (remaining.num_seconds() + 59) / 60;
+            match notify(&app, &expiry, minutes_left) {
+                Ok(()) => {
+                    notified.insert(key, (expiry.expiration, threshold));
+                }
+                Err(e) => tracing::error!("unable to show a notification: {:?}", e),
+            }
+        }
+    }
+}
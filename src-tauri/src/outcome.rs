@@ -0,0 +1,102 @@
+use aws_sdk_sso::{
+    error::ProvideErrorMetadata, operation::get_role_credentials::GetRoleCredentialsError,
+};
+use aws_sdk_sts::operation::assume_role::AssumeRoleError;
+use serde::{Deserialize, Serialize};
+
+/// What happened to one profile during a login.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub(crate) enum ProfileOutcome {
+    /// Fresh credentials were written.
+    Success,
+    /// The role is no longer assigned or may not be assumed.
+    AccessDenied(String),
+    /// SSO turned down the token of the login, logging in again should work.
+    TokenRejected(String),
+    /// The profile's settings are wrong, e.g. an unknown account or role.
+    Misconfigured(String),
+    /// AWS throttled the request, trying again later should work.
+    Throttled(String),
+    /// Skipped as the source profile of its role chain failed.
+    SourceFailed(String),
+    /// Anything else, e.g. network or file errors.
+    Failed(String),
+}
+
+impl ProfileOutcome {
+    pub(crate) fn is_success(&self) -> bool {
+        *self == ProfileOutcome::Success
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub(crate) struct ProfileResult {
+    pub(crate) profile_name: String,
+    pub(crate) outcome: ProfileOutcome,
+}
+
+/// The per-profile results of a login. The login itself succeeded, profiles
+/// that failed did so on their own without affecting the others.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub(crate) struct LoginReport {
+    pub(crate) profiles: Vec<ProfileResult>,
+}
+
+impl LoginReport {
+    pub(crate) fn push(&mut self, profile_name: &str, outcome: ProfileOutcome) {
+        self.profiles.push(ProfileResult {
+            profile_name: profile_name.to_string(),
+            outcome,
+        });
+    }
+
+    pub(crate) fn failures(&self) -> impl Iterator<Item = &ProfileResult> {
+        self.profiles.iter().filter(|res| !res.outcome.is_success())
+    }
+}
+
+/// SSO answers `UnauthorizedException` for an invalid or expired token.
+const TOKEN_REJECTED_CODE: &str = "UnauthorizedException";
+
+/// Whether SSO turned down the access token itself rather than the profile's
+/// role, meaning a new login is needed.
+pub(crate) fn is_token_rejected(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<aws_sdk_sso::error::SdkError<GetRoleCredentialsError>>()
+        .is_some_and(|err| err.code() == Some(TOKEN_REJECTED_CODE))
+}
+
+/// Sorts an error from fetching a profile's credentials into an outcome, by
+/// the code of the SSO or STS error behind it when there is one. Codes the
+/// SDK doesn't model, like SSO's `ForbiddenException` for a role that was
+/// taken away, are matched all the same.
+pub(crate) fn classify_error(e: &anyhow::Error) -> ProfileOutcome {
+    if let Some(err) = e.downcast_ref::<aws_sdk_sso::error::SdkError<GetRoleCredentialsError>>() {
+        let message = err.message().unwrap_or("no details given").to_string();
+        return match err.code() {
+            Some("ForbiddenException" | "AccessDeniedException") => {
+                ProfileOutcome::AccessDenied(message)
+            }
+            Some(TOKEN_REJECTED_CODE) => ProfileOutcome::TokenRejected(message),
+            Some("TooManyRequestsException") => ProfileOutcome::Throttled(message),
+            Some("ResourceNotFoundException" | "InvalidRequestException") => {
+                ProfileOutcome::Misconfigured(message)
+            }
+            _ => ProfileOutcome::Failed(format!("{}: {}", err, message)),
+        };
+    }
+    if let Some(err) = e.downcast_ref::<aws_sdk_sts::error::SdkError<AssumeRoleError>>() {
+        let message = err.message().unwrap_or("no details given").to_string();
+        return match err.code() {
+            Some("AccessDenied") => ProfileOutcome::AccessDenied(message),
+            Some("Throttling") => ProfileOutcome::Throttled(message),
+            Some(
+                "ValidationError"
+                | "MalformedPolicyDocument"
+                | "PackedPolicyTooLarge"
+                | "RegionDisabledException",
+            ) => ProfileOutcome::Misconfigured(message),
+            _ => ProfileOutcome::Failed(format!("{}: {}", err, message)),
+        };
+    }
+    ProfileOutcome::Failed(e.to_string())
+}
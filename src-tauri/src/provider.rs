@@ -0,0 +1,155 @@
+use std::{fs, path::PathBuf, time::SystemTime};
+
+use aws_config::Region;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aws::{config::AwsConfigSections, ini_edit::write_private, paths::butler_cache_dir},
+    cache::{cache_key_hash, get_login_from_cache, get_token_from_cache, store_token_in_cache},
+    login::{SsoToken, fetch_role_credentials, generate_aws_config, refresh_cached_token},
+    trace_err_ret,
+};
+
+/// Cached credentials this close to expiry are fetched again, so callers
+/// never receive credentials that expire before they can use them.
+const EXPIRY_BUFFER: TimeDelta = TimeDelta::minutes(5);
+
+/// The Version 1 document of the `credential_process` protocol.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ProcessCredentials {
+    version: u8,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: String,
+    expiration: DateTime<Utc>,
+}
+
+fn credentials_cache_file(profile_name: &str) -> Result<PathBuf, anyhow::Error> {
+    Ok(butler_cache_dir()?.join(format!("{}.json", cache_key_hash(profile_name))))
+}
+
+fn get_cached_credentials(profile_name: &str) -> Option<ProcessCredentials> {
+    let content = fs::read_to_string(credentials_cache_file(profile_name).ok()?).ok()?;
+    serde_json::from_str::<ProcessCredentials>(&content)
+        .ok()
+        .filter(|creds| creds.expiration - Utc::now() > EXPIRY_BUFFER)
+}
+
+fn store_cached_credentials(
+    profile_name: &str,
+    creds: &ProcessCredentials,
+) -> Result<(), anyhow::Error> {
+    let cache_file = credentials_cache_file(profile_name)?;
+    if let Some(parent) = cache_file.parent() {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder.create(parent)?;
+    }
+    write_private(&cache_file, &serde_json::to_string_pretty(creds)?)
+}
+
+/// Drops the cached credentials of a profile, e.g. after logging out.
+pub(crate) fn remove_cached_credentials(profile_name: &str) -> Result<(), anyhow::Error> {
+    let cache_file = credentials_cache_file(profile_name)?;
+    if cache_file.exists() {
+        fs::remove_file(cache_file)?;
+    }
+    Ok(())
+}
+
+/// Moves the cached credentials of a renamed profile to its new name.
+pub(crate) fn rename_cached_credentials(
+    old_name: &str,
+    new_name: &str,
+) -> Result<(), anyhow::Error> {
+    let cache_file = credentials_cache_file(old_name)?;
+    if cache_file.exists() {
+        fs::rename(cache_file, credentials_cache_file(new_name)?)?;
+    }
+    Ok(())
+}
+
+/// Gets a valid SSO token from the cache, refreshing it if needed. This never
+/// starts an interactive login, as there is nobody to approve it.
+pub(crate) async fn get_valid_token(
+    sso_oidc_client: &aws_sdk_ssooidc::Client,
+    session_name: Option<&str>,
+    sso_region: &str,
+    sso_start_url: &str,
+) -> Result<SsoToken, anyhow::Error> {
+    let cached = match session_name {
+        Some(sn) => get_token_from_cache(sn)?,
+        None => get_login_from_cache(None, sso_start_url)?.map(|cached| cached.token),
+    };
+    if let Some(token) = cached.filter(|tok| tok.expiration > SystemTime::now()) {
+        return Ok(token);
+    }
+
+    let (token, registration) = refresh_cached_token(sso_oidc_client, session_name, sso_start_url)
+        .await
+        .ok_or_else(|| trace_err_ret("SSO session has expired, log in with Awth Butler first!"))?;
+    store_token_in_cache(
+        session_name,
+        sso_start_url,
+        sso_region,
+        &registration,
+        &token,
+    )?;
+    Ok(token)
+}
+
+async fn fetch_process_credentials(
+    profile_name: &str,
+) -> Result<ProcessCredentials, anyhow::Error> {
+    let profile_set = AwsConfigSections::parse()?;
+    let prof = profile_set
+        .profiles
+        .get(profile_name)
+        .ok_or_else(|| trace_err_ret("Profile not found!"))?;
+    let (sso_region, sso_start_url) = profile_set
+        .sso_settings(prof)
+        .ok_or_else(|| trace_err_ret("No SSO settings found for profile!"))?;
+
+    let config = generate_aws_config(Region::new(sso_region.to_string())).await;
+    let sso_oidc_client = aws_sdk_ssooidc::Client::new(&config);
+    let token = get_valid_token(
+        &sso_oidc_client,
+        prof.sso_session(),
+        sso_region,
+        sso_start_url,
+    )
+    .await?;
+
+    let sso_client = aws_sdk_sso::Client::new(&config);
+    let creds = fetch_role_credentials(&sso_client, prof, &token.access_token).await?;
+
+    Ok(ProcessCredentials {
+        version: 1,
+        access_key_id: creds.access_key_id,
+        secret_access_key: creds.secret_access_key,
+        session_token: creds.session_token,
+        expiration: creds.expiration,
+    })
+}
+
+/// Prints `credential_process` credentials for a profile, served from the
+/// cache until they are close to expiring.
+pub(crate) async fn print_process_credentials(profile_name: &str) -> Result<(), anyhow::Error> {
+    let creds = match get_cached_credentials(profile_name) {
+        Some(creds) => creds,
+        None => {
+            let creds = fetch_process_credentials(profile_name).await?;
+            store_cached_credentials(profile_name, &creds)?;
+            creds
+        }
+    };
+    println!("{}", serde_json::to_string(&creds)?);
+    Ok(())
+}
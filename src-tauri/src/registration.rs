@@ -0,0 +1,149 @@
+use std::fs;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aws::{ini_edit::write_private, paths::sso_cache_dir},
+    cache::cache_key_hash,
+    login::LoginFlow,
+    trace_err_ret,
+};
+
+const CLIENT_NAME: &str = "aws-awth-butler";
+
+/// Loopback redirects may use any port, so clients register without one.
+pub(crate) const LOOPBACK_REDIRECT_URI: &str = "http://127.0.0.1/oauth/callback";
+
+/// Registrations this close to expiry are replaced instead of reused, so a
+/// long running login never straddles the expiration.
+const REREGISTER_THRESHOLD: TimeDelta = TimeDelta::days(7);
+
+/// An OIDC client registration for one SSO region and start URL.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ClientRegistration {
+    pub(crate) client_id: String,
+    pub(crate) client_secret: String,
+    pub(crate) expires_at: DateTime<Utc>,
+}
+
+impl ClientRegistration {
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+
+    fn needs_renewal(&self) -> bool {
+        self.expires_at - Utc::now() < REREGISTER_THRESHOLD
+    }
+}
+
+/// Clients for the authorization code grant are registered with other grant
+/// types and a redirect, so they are stored apart from device flow clients.
+fn registration_file_name(sso_region: &str, sso_start_url: &str, flow: LoginFlow) -> String {
+    let key = match flow {
+        LoginFlow::DeviceCode => format!("{}|{}|{}", CLIENT_NAME, sso_region, sso_start_url),
+        LoginFlow::AuthorizationCode => format!(
+            "{}|{}|{}|authorization_code",
+            CLIENT_NAME, sso_region, sso_start_url
+        ),
+    };
+    format!("{}.json", cache_key_hash(&key))
+}
+
+pub(crate) fn load_registration(
+    sso_region: &str,
+    sso_start_url: &str,
+    flow: LoginFlow,
+) -> Result<Option<ClientRegistration>, anyhow::Error> {
+    let reg_file = sso_cache_dir()?.join(registration_file_name(sso_region, sso_start_url, flow));
+    if !reg_file.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&fs::read_to_string(reg_file)?)?))
+}
+
+fn store_registration(
+    sso_region: &str,
+    sso_start_url: &str,
+    flow: LoginFlow,
+    registration: &ClientRegistration,
+) -> Result<(), anyhow::Error> {
+    let cache_dir = sso_cache_dir()?;
+    fs::create_dir_all(&cache_dir)?;
+    // the client secret is only readable by the user, like the token cache
+    write_private(
+        &cache_dir.join(registration_file_name(sso_region, sso_start_url, flow)),
+        &serde_json::to_string_pretty(registration)?,
+    )
+}
+
+async fn register_client(
+    sso_oidc_client: &aws_sdk_ssooidc::Client,
+    sso_start_url: &str,
+    flow: LoginFlow,
+) -> Result<ClientRegistration, anyhow::Error> {
+    let request = sso_oidc_client
+        .register_client()
+        .client_name(CLIENT_NAME)
+        .client_type("public")
+        .scopes("sso:account:access");
+    let request = match flow {
+        LoginFlow::DeviceCode => request,
+        LoginFlow::AuthorizationCode => request
+            .grant_types("authorization_code")
+            .grant_types("refresh_token")
+            .redirect_uris(LOOPBACK_REDIRECT_URI)
+            .issuer_url(sso_start_url),
+    };
+    let output = request.send().await?;
+    Ok(ClientRegistration {
+        client_id: output
+            .client_id()
+            .ok_or_else(|| trace_err_ret("Client ID not found!"))?
+            .to_string(),
+        client_secret: output
+            .client_secret()
+            .ok_or_else(|| trace_err_ret("Client Secret not found!"))?
+            .to_string(),
+        expires_at: DateTime::from_timestamp(output.client_secret_expires_at(), 0)
+            .ok_or_else(|| trace_err_ret("Invalid timestamp!"))?,
+    })
+}
+
+/// Returns the stored registration for this region, start URL and login flow,
+/// registering a new client when there is none or the stored one is about to
+/// expire.
+pub(crate) async fn get_or_register_client(
+    sso_oidc_client: &aws_sdk_ssooidc::Client,
+    sso_region: &str,
+    sso_start_url: &str,
+    flow: LoginFlow,
+) -> Result<ClientRegistration, anyhow::Error> {
+    match load_registration(sso_region, sso_start_url, flow) {
+        Ok(Some(registration)) if !registration.needs_renewal() => {
+            tracing::info!(
+                "reusing client registration for {}, expires at {}",
+                sso_start_url,
+                registration.expires_at
+            );
+            return Ok(registration);
+        }
+        Ok(Some(registration)) => tracing::info!(
+            "client registration for {} expires at {}, registering a new one",
+            sso_start_url,
+            registration.expires_at
+        ),
+        Ok(None) => {}
+        Err(e) => tracing::warn!("unable to read stored client registration: {:?}", e),
+    }
+
+    let registration = register_client(sso_oidc_client, sso_start_url, flow).await?;
+    store_registration(sso_region, sso_start_url, flow, &registration)?;
+    tracing::info!(
+        "registered new client for {}, expires at {}",
+        sso_start_url,
+        registration.expires_at
+    );
+    Ok(registration)
+}
@@ -0,0 +1,160 @@
+use std::time::{Duration, SystemTime};
+
+use aws_config::Region;
+use chrono::{TimeDelta, Utc};
+use tauri::{AppHandle, Manager};
+use tokio::sync::Mutex;
+
+use crate::{
+    ButlerState,
+    aws::{
+        config::{AwsConfigSections, Profile},
+        credentials::{get_credentials_for_profile, store_credentials_for_profile},
+    },
+    cache::{get_login_from_cache, get_token_from_cache},
+    login::{SsoToken, fetch_role_credentials, generate_aws_config},
+    settings::Settings,
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_LEAD_TIME: TimeDelta = TimeDelta::minutes(10);
+
+/// Opts a profile, or every profile of an sso-session, into auto refresh.
+const AUTO_REFRESH_KEY: &str = "butler_auto_refresh";
+/// Minutes before expiry at which role credentials get refreshed.
+const LEAD_TIME_KEY: &str = "butler_refresh_lead_minutes";
+
+/// A profile opted into auto refresh, with everything resolved from its
+/// session so the state lock isn't held while talking to AWS.
+struct RefreshTarget {
+    profile: Profile,
+    session_name: Option<String>,
+    sso_region: String,
+    sso_start_url: String,
+    lead_time: TimeDelta,
+}
+
+/// The lead time of a profile opted into auto refresh, `None` for the others.
+/// Keys in the AWS config win over the app settings, either way profile
+/// settings take precedence over the session ones.
+pub(crate) fn auto_refresh_lead_time(
+    profile_set: &AwsConfigSections,
+    settings: &Settings,
+    prof: &Profile,
+) -> Option<TimeDelta> {
+    let session_name = prof.sso_session();
+    let session = session_name.and_then(|sn| profile_set.sessions.get(sn));
+    let setting = |key: &str| prof.get(key).or_else(|| session.and_then(|s| s.get(key)));
+
+    let auto_refresh = match setting(AUTO_REFRESH_KEY) {
+        Some(value) => value.eq_ignore_ascii_case("true"),
+        None => settings
+            .lookup(Some(prof.name()), session_name, |section| {
+                section.auto_refresh
+            })
+            .unwrap_or(false),
+    };
+    if !auto_refresh {
+        return None;
+    }
+
+    let lead_time = setting(LEAD_TIME_KEY)
+        .and_then(|v| v.parse::<u64>().ok())
+        .or_else(|| {
+            settings.lookup(Some(prof.name()), session_name, |section| {
+                section.refresh_lead_minutes
+            })
+        })
+        .map(|minutes| TimeDelta::minutes(minutes as i64))
+        .unwrap_or(DEFAULT_LEAD_TIME);
+    Some(lead_time)
+}
+
+fn collect_targets(profile_set: &AwsConfigSections, settings: &Settings) -> Vec<RefreshTarget> {
+    let mut targets = Vec::new();
+    for prof in profile_set.profiles.values() {
+        let Some(lead_time) = auto_refresh_lead_time(profile_set, settings, prof) else {
+            continue;
+        };
+
+        let Some((sso_region, sso_start_url)) = profile_set.sso_settings(prof) else {
+            tracing::warn!(
+                "profile {} is set to auto refresh but has no sso settings",
+                prof.name()
+            );
+            continue;
+        };
+
+        targets.push(RefreshTarget {
+            profile: prof.clone(),
+            session_name: prof.sso_session().map(|sn| sn.to_string()),
+            sso_region: sso_region.to_string(),
+            sso_start_url: sso_start_url.to_string(),
+            lead_time,
+        });
+    }
+    targets
+}
+
+fn cached_token_for(target: &RefreshTarget) -> Result<Option<SsoToken>, anyhow::Error> {
+    match &target.session_name {
+        Some(sn) => get_token_from_cache(sn),
+        None => Ok(get_login_from_cache(None, &target.sso_start_url)?.map(|cached| cached.token)),
+    }
+}
+
+async fn refresh_target(target: &RefreshTarget) -> Result<(), anyhow::Error> {
+    let profile_name = target.profile.name();
+
+    // only credentials butler has written before are kept alive
+    let Some(creds) = get_credentials_for_profile(profile_name)? else {
+        return Ok(());
+    };
+    if creds.expiration - Utc::now() > target.lead_time {
+        return Ok(());
+    }
+
+    let Some(token) = cached_token_for(target)?.filter(|tok| tok.expiration > SystemTime::now())
+    else {
+        tracing::info!(
+            "sso token for profile {} has expired, skipping auto refresh",
+            profile_name
+        );
+        return Ok(());
+    };
+
+    let config = generate_aws_config(Region::new(target.sso_region.clone())).await;
+    let sso_client = aws_sdk_sso::Client::new(&config);
+    let creds = fetch_role_credentials(&sso_client, &target.profile, &token.access_token).await?;
+    store_credentials_for_profile(profile_name, &creds)?;
+    tracing::info!(
+        "auto refreshed role credentials for profile {}",
+        profile_name
+    );
+    Ok(())
+}
+
+/// Periodically refreshes role credentials of opted-in profiles before they
+/// expire, as long as their SSO token is still valid.
+pub(crate) async fn run_auto_refresh(app: AppHandle) {
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        ticker.tick().await;
+
+        let targets = {
+            let state = app.state::<Mutex<ButlerState>>();
+            let state = state.lock().await;
+            collect_targets(&state.aws_profiles, &state.settings)
+        };
+
+        for target in &targets {
+            if let Err(e) = refresh_target(target).await {
+                tracing::error!(
+                    "auto refresh failed for profile {}: {:?}",
+                    target.profile.name(),
+                    e
+                );
+            }
+        }
+    }
+}
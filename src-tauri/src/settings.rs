@@ -0,0 +1,336 @@
+use std::{collections::BTreeMap, fs, io::ErrorKind, path::PathBuf, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    aws::ini_edit::write_atomically,
+    login::{LoginBrowser, LoginFlow},
+    trace_err_ret,
+};
+
+const APP_DIR_NAME: &str = "Awth Butler";
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+/// The schema version this build reads and writes. Bump it whenever old
+/// files would no longer deserialize as intended, and add the step upgrading
+/// the previous version to `MIGRATIONS`.
+pub(crate) const SETTINGS_VERSION: u64 = 1;
+
+/// Upgrades the raw JSON of a file by one version, so fields that were renamed
+/// or moved can still be read.
+type Migration = fn(&mut serde_json::Map<String, Value>) -> Result<(), anyhow::Error>;
+
+/// Entry `i` upgrades version `i` to `i + 1`.
+const MIGRATIONS: [Migration; SETTINGS_VERSION as usize] = [
+    // files from before the version field have the layout of version 1
+    |_| Ok(()),
+];
+
+/// Role credentials from SSO last at most 12 hours, refreshing earlier than
+/// that would refresh on every check.
+const MAX_REFRESH_LEAD_MINUTES: u64 = 12 * 60;
+
+/// Settings for one sso-session or profile, or the defaults for all of them.
+/// Unset fields fall through to the next, less specific level.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub(crate) struct SectionSettings {
+    pub(crate) login_flow: Option<LoginFlow>,
+    /// Same values as `butler_login_browser`.
+    pub(crate) login_browser: Option<String>,
+    pub(crate) login_timeout_minutes: Option<u64>,
+    pub(crate) auto_refresh: Option<bool>,
+    pub(crate) refresh_lead_minutes: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct NotificationSettings {
+    pub(crate) enabled: bool,
+    /// Minutes before expiry at which to warn, every threshold warns once.
+    pub(crate) thresholds_minutes: Vec<u64>,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            thresholds_minutes: vec![30, 5],
+        }
+    }
+}
+
+/// The app's own settings, kept next to its log file. Keys set in
+/// `~/.aws/config` take precedence, as they are shared with the CLI and other
+/// machines.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct Settings {
+    pub(crate) version: u64,
+    pub(crate) defaults: SectionSettings,
+    /// Overrides by sso-session name.
+    pub(crate) sessions: BTreeMap<String, SectionSettings>,
+    /// Overrides by profile name.
+    pub(crate) profiles: BTreeMap<String, SectionSettings>,
+    pub(crate) notifications: NotificationSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            defaults: Default::default(),
+            sessions: Default::default(),
+            profiles: Default::default(),
+            notifications: Default::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Looks a setting up for a profile, then its sso-session, then in the
+    /// defaults.
+    pub(crate) fn lookup<T>(
+        &self,
+        profile_name: Option<&str>,
+        session_name: Option<&str>,
+        field: impl Fn(&SectionSettings) -> Option<T>,
+    ) -> Option<T> {
+        profile_name
+            .and_then(|name| self.profiles.get(name))
+            .and_then(&field)
+            .or_else(|| {
+                session_name
+                    .and_then(|name| self.sessions.get(name))
+                    .and_then(&field)
+            })
+            .or_else(|| field(&self.defaults))
+    }
+
+    /// Fails with every problem found, so they can all be fixed in one go.
+    pub(crate) fn validate(&self) -> Result<(), anyhow::Error> {
+        let mut problems = Vec::new();
+        if self.version != SETTINGS_VERSION {
+            problems.push(format!(
+                "version is {}, expected {}",
+                self.version, SETTINGS_VERSION
+            ));
+        }
+        validate_section("defaults", &self.defaults, &mut problems);
+        for (kind, sections) in [("session", &self.sessions), ("profile", &self.profiles)] {
+            for (name, section) in sections {
+                if name.trim().is_empty() {
+                    problems.push(format!("a {} override has no name", kind));
+                }
+                validate_section(&format!("{} {}", kind, name), section, &mut problems);
+            }
+        }
+        let thresholds = &self.notifications.thresholds_minutes;
+        if thresholds.contains(&0) {
+            problems.push("notification thresholds must be at least 1 minute".to_string());
+        }
+        if (1..thresholds.len()).any(|i| thresholds[..i].contains(&thresholds[i])) {
+            problems.push("notification thresholds must not repeat".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(trace_err_ret(&format!(
+                "Invalid settings: {}",
+                problems.join("; ")
+            )))
+        }
+    }
+}
+
+fn validate_section(scope: &str, section: &SectionSettings, problems: &mut Vec<String>) {
+    if let Some(browser) = &section.login_browser
+        && LoginBrowser::from_str(browser).is_err()
+    {
+        problems.push(format!("{}: login browser is empty", scope));
+    }
+    if section.login_timeout_minutes == Some(0) {
+        problems.push(format!(
+            "{}: login timeout must be at least 1 minute",
+            scope
+        ));
+    }
+    if let Some(lead) = section.refresh_lead_minutes
+        && !(1..=MAX_REFRESH_LEAD_MINUTES).contains(&lead)
+    {
+        problems.push(format!(
+            "{}: refresh lead time must be between 1 and {} minutes",
+            scope, MAX_REFRESH_LEAD_MINUTES
+        ));
+    }
+}
+
+/// The app's config directory, the one its log file goes to. Resolved here
+/// without creating it, as the CLI and credential process read the settings
+/// too, also on machines that have no config directory at all.
+fn settings_dir() -> Result<PathBuf, anyhow::Error> {
+    dirs::config_dir()
+        .map(|dir| dir.join(APP_DIR_NAME))
+        .ok_or_else(|| trace_err_ret("Unable to find a config directory for the settings!"))
+}
+
+/// Brings a file of any earlier schema version up to the current one. Files
+/// without a version are version 0.
+fn migrate(value: Value) -> Result<(Value, u64), anyhow::Error> {
+    let Value::Object(mut fields) = value else {
+        return Err(trace_err_ret("Settings file is not a JSON object!"));
+    };
+    let version = match fields.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| trace_err_ret("Settings file has an invalid schema version!"))?,
+    };
+    if version > SETTINGS_VERSION {
+        return Err(trace_err_ret(&format!(
+            "Settings file has schema version {}, this version of butler reads up to {}!",
+            version, SETTINGS_VERSION
+        )));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut fields)?;
+    }
+    fields.insert("version".to_string(), SETTINGS_VERSION.into());
+    Ok((Value::Object(fields), version))
+}
+
+/// Reads the settings file, defaults when there is none. Files of an earlier
+/// schema version are migrated and written back, keeping the original next
+/// to it.
+pub(crate) fn load_settings() -> Result<Settings, anyhow::Error> {
+    let dir = settings_dir()?;
+    let path = dir.join(SETTINGS_FILE_NAME);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Settings::default()),
+        Err(e) => return Err(e.into()),
+    };
+    let (value, from_version) = migrate(serde_json::from_str(&contents)?)?;
+    let settings = serde_json::from_value::<Settings>(value)?;
+    settings.validate()?;
+
+    if from_version != SETTINGS_VERSION {
+        fs::copy(&path, dir.join(format!("settings.v{}.json", from_version)))?;
+        store_settings(&settings)?;
+        tracing::info!(
+            "migrated settings from version {} to {}",
+            from_version,
+            SETTINGS_VERSION
+        );
+    }
+    Ok(settings)
+}
+
+/// Validates and writes the settings file.
+pub(crate) fn store_settings(settings: &Settings) -> Result<(), anyhow::Error> {
+    settings.validate()?;
+    let dir = settings_dir()?;
+    fs::create_dir_all(&dir)?;
+    write_atomically(
+        &dir.join(SETTINGS_FILE_NAME),
+        &serde_json::to_string_pretty(settings)?,
+    )
+}
+
+/// The settings to start with. A broken file falls back to the defaults
+/// without being overwritten, so it can still be fixed by hand.
+pub(crate) fn load_settings_or_default() -> Settings {
+    load_settings().unwrap_or_else(|e| {
+        tracing::error!("unable to load settings, using the defaults: {:?}", e);
+        Settings::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn files_from_a_newer_butler_are_rejected() {
+        assert!(migrate(json!({ "version": SETTINGS_VERSION + 1 })).is_err());
+        assert!(migrate(json!({ "version": "1" })).is_err());
+        assert!(migrate(json!([])).is_err());
+    }
+
+    #[test]
+    fn files_without_a_version_are_migrated_from_version_0() {
+        let (value, from_version) =
+            migrate(json!({ "defaults": { "auto_refresh": true } })).unwrap();
+        assert_eq!(from_version, 0);
+        let settings = serde_json::from_value::<Settings>(value).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.defaults.auto_refresh, Some(true));
+        settings.validate().unwrap();
+    }
+
+    #[test]
+    fn current_files_are_left_as_they_are() {
+        let current = json!({ "version": SETTINGS_VERSION, "profiles": {} });
+        assert_eq!(
+            migrate(current.clone()).unwrap(),
+            (current, SETTINGS_VERSION)
+        );
+    }
+
+    #[test]
+    fn profiles_override_sessions_override_defaults() {
+        let section = |minutes| SectionSettings {
+            login_timeout_minutes: Some(minutes),
+            ..Default::default()
+        };
+        let settings = Settings {
+            defaults: section(30),
+            sessions: BTreeMap::from([("my-sso".to_string(), section(20))]),
+            profiles: BTreeMap::from([("dev".to_string(), section(10))]),
+            ..Default::default()
+        };
+        let timeout = |profile, session| {
+            settings.lookup(profile, session, |section| section.login_timeout_minutes)
+        };
+        assert_eq!(timeout(Some("dev"), Some("my-sso")), Some(10));
+        assert_eq!(timeout(Some("prod"), Some("my-sso")), Some(20));
+        assert_eq!(timeout(Some("prod"), Some("other-sso")), Some(30));
+        assert_eq!(timeout(None, None), Some(30));
+        assert_eq!(
+            settings.lookup(Some("dev"), Some("my-sso"), |section| section.auto_refresh),
+            None
+        );
+    }
+
+    #[test]
+    fn validation_reports_every_problem() {
+        let settings = Settings {
+            version: SETTINGS_VERSION + 1,
+            defaults: SectionSettings {
+                login_timeout_minutes: Some(0),
+                refresh_lead_minutes: Some(MAX_REFRESH_LEAD_MINUTES + 1),
+                ..Default::default()
+            },
+            notifications: NotificationSettings {
+                enabled: true,
+                thresholds_minutes: vec![5, 0, 5],
+            },
+            ..Default::default()
+        };
+        let message = settings.validate().unwrap_err().to_string();
+        for problem in [
+            "version",
+            "timeout",
+            "lead time",
+            "at least 1 minute",
+            "repeat",
+        ] {
+            assert!(message.contains(problem), "{} in {}", problem, message);
+        }
+        Settings::default().validate().unwrap();
+    }
+}
@@ -0,0 +1,479 @@
+use clap::{ArgAction, Parser, Subcommand};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    aws::config::{AwsConfigSections, NestedProperties},
+    diagnostics::{Severity, diagnose},
+    discovery::discover_session,
+    fetch_profiles_new,
+    generator::{GenerateOptions, PlannedAction, apply_profiles, preview_profiles},
+    handlers::build_butler_config,
+    login::{
+        AuthPrompt, ProfileSelection, profile_login, profile_logout, sso_session_login,
+        sso_session_logout,
+    },
+    outcome::{LoginReport, ProfileOutcome},
+    settings::load_settings_or_default,
+    trace_err_ret,
+    utils::describe_expiry,
+};
+
+#[derive(Parser)]
+#[command(
+    name = "awth-butler-cli",
+    version,
+    about = "AWS SSO logins from the terminal"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Log more detail to stderr, repeat for even more
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Log into an sso-session or a single profile
+    Login {
+        #[command(subcommand)]
+        target: LoginTarget,
+    },
+    /// List every session and profile with its freshness
+    List,
+    /// Show the status of a single session or profile
+    Status { name: String },
+    /// Sign out of a session or profile and remove its credentials
+    Logout {
+        #[command(subcommand)]
+        target: Target,
+    },
+    /// Add profiles for the roles of an sso-session from a naming template,
+    /// showing the changes unless --apply is given
+    Generate {
+        session: String,
+        /// e.g. "{account_name}-{role_name}", also knows {account_id} and
+        /// {session_name}
+        #[arg(long)]
+        template: String,
+        /// Default region for the generated profiles
+        #[arg(long)]
+        region: Option<String>,
+        /// Default output format for the generated profiles
+        #[arg(long)]
+        output: Option<String>,
+        /// Write the profiles instead of only showing them
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Check the config for problems, failing if any would break a login
+    Lint,
+    /// List the accounts and roles available through an sso-session
+    Discover {
+        session: String,
+        /// Ask AWS again instead of using the cached results
+        #[arg(long)]
+        refresh: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum LoginTarget {
+    /// An sso-session and every profile using it, or only some of them
+    Session {
+        name: String,
+        /// Only log in this profile, can be given more than once
+        #[arg(long = "profile", conflicts_with_all = ["tag", "filter"])]
+        profiles: Vec<String>,
+        /// Only log in profiles listing this tag in butler_tags
+        #[arg(long, conflicts_with = "filter")]
+        tag: Option<String>,
+        /// Only log in profiles whose name matches, e.g. "prod-*"
+        #[arg(long)]
+        filter: Option<String>,
+    },
+    /// A single profile
+    Profile { name: String },
+}
+
+#[derive(Subcommand)]
+enum Target {
+    /// An sso-session and every profile using it
+    Session { name: String },
+    /// A single profile
+    Profile { name: String },
+}
+
+/// Prints the device authorization instead of opening a webview, so logins
+/// work over ssh and on machines without a display.
+struct TerminalPrompt;
+
+impl AuthPrompt for TerminalPrompt {
+    fn open(
+        &mut self,
+        verification_uri: &str,
+        user_code: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        println!("To sign in, open the following URL in a browser:\n");
+        println!("    {}\n", verification_uri);
+        if let Some(user_code) = user_code {
+            println!("and confirm the code {}.\n", user_code);
+        }
+        println!("Waiting for approval...");
+        Ok(())
+    }
+
+    fn check_cancelled(&mut self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+}
+
+/// Prints the outcome of every profile, failing if any of them failed.
+fn print_report(report: &LoginReport) -> Result<(), anyhow::Error> {
+    for result in &report.profiles {
+        let outcome = match &result.outcome {
+            ProfileOutcome::Success => "ok".to_string(),
+            ProfileOutcome::AccessDenied(reason) => format!("access denied: {}", reason),
+            ProfileOutcome::TokenRejected(reason) => format!("log in again: {}", reason),
+            ProfileOutcome::Misconfigured(reason) => format!("misconfigured: {}", reason),
+            ProfileOutcome::Throttled(reason) => format!("throttled: {}", reason),
+            ProfileOutcome::SourceFailed(reason) => format!("skipped: {}", reason),
+            ProfileOutcome::Failed(reason) => format!("failed: {}", reason),
+        };
+        println!("  {:<32} {}", result.profile_name, outcome);
+    }
+
+    let failed = report.failures().count();
+    if failed > 0 {
+        return Err(trace_err_ret(&format!(
+            "{} of {} profiles failed",
+            failed,
+            report.profiles.len()
+        )));
+    }
+    Ok(())
+}
+
+fn list() -> Result<(), anyhow::Error> {
+    let config = build_butler_config(&fetch_profiles_new()?, &load_settings_or_default())?;
+
+    let mut sessions = config.sessions;
+    sessions.sort_by(|a, b| a.session_name.cmp(&b.session_name));
+    println!("Sessions:");
+    for sess in &sessions {
+        println!(
+            "  {:<32} {}",
+            sess.session_name,
+            describe_expiry(sess.fresh, sess.session_expiration)
+        );
+    }
+
+    let mut sso_profiles = config.sso_profiles;
+    sso_profiles.sort_by(|a, b| a.profile_name.cmp(&b.profile_name));
+    println!("\nSession Profiles:");
+    for prof in &sso_profiles {
+        println!(
+            "  {:<32} {:<24} {}",
+            prof.profile_name,
+            prof.session_name,
+            describe_expiry(prof.fresh, prof.profile_expiration)
+        );
+    }
+
+    let mut legacy_profiles = config.legacy_profiles;
+    legacy_profiles.sort_by(|a, b| a.profile_name.cmp(&b.profile_name));
+    println!("\nLegacy Profiles:");
+    for prof in &legacy_profiles {
+        println!(
+            "  {:<32} {}",
+            prof.profile_name,
+            describe_expiry(prof.fresh, prof.profile_expiration)
+        );
+    }
+
+    let mut chained_profiles = config.chained_profiles;
+    chained_profiles.sort_by(|a, b| a.profile_name.cmp(&b.profile_name));
+    println!("\nChained Profiles:");
+    for prof in &chained_profiles {
+        println!(
+            "  {:<32} {:<24} {}",
+            prof.profile_name,
+            prof.source_profile,
+            describe_expiry(prof.fresh, prof.profile_expiration)
+        );
+    }
+    Ok(())
+}
+
+/// Prints the nested settings of a profile and the `[services ...]` section
+/// it refers to, so endpoint overrides are visible next to its status.
+fn print_profile_settings(profile_set: &AwsConfigSections, name: &str) {
+    let Some(profile) = profile_set.profiles.get(name) else {
+        return;
+    };
+    let mut nested = profile.nested.keys().collect::<Vec<_>>();
+    nested.sort();
+    for key in nested {
+        println!(
+            "{:<14}{}",
+            format!("{}:", key),
+            format_nested(profile.nested(key))
+        );
+    }
+    match (profile.services(), profile_set.services_for(profile)) {
+        (Some(_), Some(services)) => {
+            let mut service_ids = services.services.keys().collect::<Vec<_>>();
+            service_ids.sort();
+            println!("Services:     {}", services.name());
+            for service_id in service_ids {
+                println!(
+                    "  {:<12}{}",
+                    service_id,
+                    format_nested(services.service(service_id))
+                );
+            }
+        }
+        (Some(missing), None) => println!("Services:     {} (section not found)", missing),
+        (None, _) => {}
+    }
+}
+
+fn format_nested(properties: Option<&NestedProperties>) -> String {
+    let mut pairs = properties
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| format!("{} = {}", key, value))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs.join(", ")
+}
+
+fn status(name: &str) -> Result<(), anyhow::Error> {
+    let profile_set = fetch_profiles_new()?;
+    let config = build_butler_config(&profile_set, &load_settings_or_default())?;
+
+    if let Some(sess) = config.sessions.iter().find(|s| s.session_name == name) {
+        println!("Session:      {}", sess.session_name);
+        println!(
+            "Token:        {}",
+            describe_expiry(sess.fresh, sess.session_expiration)
+        );
+        if let Some(reg_exp) = sess.registration_expiration {
+            println!("Registration: expires {}", reg_exp.format("%Y-%m-%d"));
+        }
+        println!("Profiles:     {}", sess.profile_names.join(", "));
+    } else if let Some(prof) = config.sso_profiles.iter().find(|p| p.profile_name == name) {
+        println!("Profile:      {}", prof.profile_name);
+        println!("Session:      {}", prof.session_name);
+        println!(
+            "Credentials:  {}",
+            describe_expiry(prof.fresh, prof.profile_expiration)
+        );
+        print_profile_settings(&profile_set, name);
+    } else if let Some(prof) = config
+        .legacy_profiles
+        .iter()
+        .find(|p| p.profile_name == name)
+    {
+        println!("Profile:      {}", prof.profile_name);
+        println!(
+            "Credentials:  {}",
+            describe_expiry(prof.fresh, prof.profile_expiration)
+        );
+        if let Some(reg_exp) = prof.registration_expiration {
+            println!("Registration: expires {}", reg_exp.format("%Y-%m-%d"));
+        }
+        print_profile_settings(&profile_set, name);
+    } else if let Some(prof) = config
+        .chained_profiles
+        .iter()
+        .find(|p| p.profile_name == name)
+    {
+        println!("Profile:      {}", prof.profile_name);
+        println!("Source:       {}", prof.source_profile);
+        println!("Login via:    {}", prof.root_profile);
+        println!(
+            "Credentials:  {}",
+            describe_expiry(prof.fresh, prof.profile_expiration)
+        );
+        print_profile_settings(&profile_set, name);
+    } else {
+        return Err(trace_err_ret("No session or profile found with that name!"));
+    }
+    Ok(())
+}
+
+fn lint() -> Result<(), anyhow::Error> {
+    let findings = diagnose(&fetch_profiles_new()?);
+    for finding in &findings {
+        let severity = match finding.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        };
+        println!("{}: {}", severity, finding.message);
+        println!("  at   {}", finding.location);
+        println!("  hint {}\n", finding.hint);
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(trace_err_ret(&format!(
+            "{} errors found in the config",
+            errors
+        )));
+    }
+    println!("{} findings, no errors", findings.len());
+    Ok(())
+}
+
+async fn discover(session_name: &str, refresh: bool) -> Result<(), anyhow::Error> {
+    let discovery = discover_session(&fetch_profiles_new()?, session_name, refresh).await?;
+    println!(
+        "Discovered {}",
+        discovery.discovered_at.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+    for account in &discovery.accounts {
+        println!(
+            "\n{} {} {}",
+            account.account_id,
+            account.account_name.as_deref().unwrap_or("-"),
+            account.email_address.as_deref().unwrap_or("")
+        );
+        for role_name in &account.role_names {
+            println!("  {}", role_name);
+        }
+    }
+    Ok(())
+}
+
+async fn generate(options: &GenerateOptions, apply: bool) -> Result<(), anyhow::Error> {
+    let profile_set = fetch_profiles_new()?;
+    let planned = preview_profiles(&profile_set, options).await?;
+    for plan in &planned {
+        let action = match &plan.action {
+            PlannedAction::Create => "create".to_string(),
+            PlannedAction::Unchanged => "unchanged".to_string(),
+            PlannedAction::NameTaken => "skip, name taken".to_string(),
+            PlannedAction::AlreadyConfigured(existing) => format!("skip, same as {}", existing),
+        };
+        println!(
+            "  {:<40} {:<14} {:<28} {}",
+            plan.profile_name, plan.account_id, plan.role_name, action
+        );
+    }
+
+    let to_create = planned
+        .into_iter()
+        .filter(|plan| plan.action == PlannedAction::Create)
+        .map(|plan| plan.profile_name)
+        .collect::<Vec<_>>();
+    if !apply {
+        println!(
+            "\n{} profiles would be created, rerun with --apply to write them",
+            to_create.len()
+        );
+        return Ok(());
+    }
+    let written = apply_profiles(&profile_set, options, &to_create).await?;
+    println!("\nCreated {} profiles", written.len());
+    Ok(())
+}
+
+/// Logs to stderr so output stays clean. `-v` raises the level, without it
+/// `RUST_LOG` applies and only warnings are shown by default.
+fn setup_logging(verbose: u8) {
+    let filter = match verbose {
+        0 => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+        1 => EnvFilter::new("warn,awth_butler_lib=info"),
+        2 => EnvFilter::new("warn,awth_butler_lib=debug"),
+        _ => EnvFilter::new("awth_butler_lib=trace"),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+pub(crate) async fn run_cli() -> Result<(), anyhow::Error> {
+    let cli = Cli::parse();
+    setup_logging(cli.verbose);
+    match cli.command {
+        Command::Login { target } => {
+            let profile_set = fetch_profiles_new()?;
+            let settings = load_settings_or_default();
+            match &target {
+                LoginTarget::Session {
+                    name,
+                    profiles,
+                    tag,
+                    filter,
+                } => {
+                    let selection = match (tag, filter) {
+                        (Some(tag), _) => ProfileSelection::Tag(tag.clone()),
+                        (_, Some(filter)) => ProfileSelection::Filter(filter.clone()),
+                        _ if !profiles.is_empty() => ProfileSelection::Names(profiles.clone()),
+                        _ => ProfileSelection::All,
+                    };
+                    let report = sso_session_login(
+                        &profile_set,
+                        &settings,
+                        name,
+                        &selection,
+                        &mut TerminalPrompt,
+                    )
+                    .await?;
+                    println!("Logged into session {}", name);
+                    print_report(&report)?;
+                }
+                LoginTarget::Profile { name } => {
+                    let report =
+                        profile_login(&profile_set, &settings, name, &mut TerminalPrompt).await?;
+                    println!("Logged into profile {}", name);
+                    print_report(&report)?;
+                }
+            }
+        }
+        Command::List => list()?,
+        Command::Status { name } => status(&name)?,
+        Command::Lint => lint()?,
+        Command::Discover { session, refresh } => discover(&session, refresh).await?,
+        Command::Generate {
+            session,
+            template,
+            region,
+            output,
+            apply,
+        } => {
+            let options = GenerateOptions {
+                session_name: session,
+                template,
+                region,
+                output,
+            };
+            generate(&options, apply).await?
+        }
+        Command::Logout { target } => {
+            let profile_set = fetch_profiles_new()?;
+            match &target {
+                Target::Session { name } => {
+                    sso_session_logout(&profile_set, name).await?;
+                    println!("Logged out of session {}", name);
+                }
+                Target::Profile { name } => {
+                    profile_logout(&profile_set, name).await?;
+                    println!("Logged out of profile {}", name);
+                }
+            }
+        }
+    }
+    Ok(())
+}
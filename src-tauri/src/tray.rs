@@ -0,0 +1,395 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use tauri::{
+    AppHandle, Manager,
+    menu::{Menu, MenuBuilder, MenuEvent, MenuItemBuilder, Submenu, SubmenuBuilder},
+    tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
+};
+use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+use tokio::sync::Mutex;
+
+use crate::{
+    ButlerState,
+    handlers::{
+        ButlerSsoConfig, LoginType, build_butler_config, emit_progress, run_login, spawn_login,
+    },
+    inflight::LoginTarget,
+    login::{AuthPrompt, LoginStep, ProfileSelection, profile_logout, sso_session_logout},
+    trace_err_ret,
+    utils::describe_expiry,
+};
+
+const TRAY_ID: &str = "main";
+const SHOW_ID: &str = "show";
+const QUIT_ID: &str = "quit";
+
+/// The time left shown in the menu goes stale, so it's rebuilt this often
+/// even when nothing changed.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+enum TrayAction {
+    Login,
+    Refresh,
+    Logout,
+}
+
+impl TrayAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            TrayAction::Login => "login",
+            TrayAction::Refresh => "refresh",
+            TrayAction::Logout => "logout",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            TrayAction::Login => "Log In",
+            TrayAction::Refresh => "Refresh Credentials",
+            TrayAction::Logout => "Log Out",
+        }
+    }
+}
+
+fn menu_id(action: TrayAction, login_type: &LoginType, name: &str) -> String {
+    let kind = match login_type {
+        LoginType::SsoSession => "session",
+        LoginType::LegacyProfile => "profile",
+    };
+    format!("{}:{}:{}", action.as_str(), kind, name)
+}
+
+fn parse_menu_id(id: &str) -> Option<(TrayAction, LoginType, &str)> {
+    let mut parts = id.splitn(3, ':');
+    let action = match parts.next()? {
+        "login" => TrayAction::Login,
+        "refresh" => TrayAction::Refresh,
+        "logout" => TrayAction::Logout,
+        _ => return None,
+    };
+    let login_type = match parts.next()? {
+        "session" => LoginType::SsoSession,
+        "profile" => LoginType::LegacyProfile,
+        _ => return None,
+    };
+    Some((action, login_type, parts.next()?))
+}
+
+/// Renews role credentials with the cached SSO token only, a login that needs
+/// the user's approval fails instead of opening a browser. Progress still
+/// goes to the main window like for any other login.
+struct SilentPrompt {
+    app: AppHandle,
+    login_id: u64,
+    target: LoginTarget,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl AuthPrompt for SilentPrompt {
+    fn open(
+        &mut self,
+        _verification_uri: &str,
+        _user_code: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        Err(trace_err_ret(
+            "The SSO session has expired, log in to refresh its credentials!",
+        ))
+    }
+
+    fn check_cancelled(&mut self) -> Result<(), anyhow::Error> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(trace_err_ret("Refresh cancelled!"));
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn progress(&mut self, step: LoginStep) {
+        emit_progress(&self.app, self.login_id, &self.target, step);
+    }
+
+    fn interactive(&self) -> bool {
+        false
+    }
+}
+
+/// A submenu for a session or legacy profile with its status, the status of
+/// the profiles below it and the quick actions.
+fn target_submenu(
+    app: &AppHandle,
+    login_type: LoginType,
+    name: &str,
+    status: &str,
+    profiles: &[(String, String)],
+) -> tauri::Result<Submenu<tauri::Wry>> {
+    let mut submenu = SubmenuBuilder::new(app, format!("{} ({})", name, status));
+    for (profile_name, profile_status) in profiles {
+        submenu = submenu.item(
+            &MenuItemBuilder::new(format!("{}: {}", profile_name, profile_status))
+                .enabled(false)
+                .build(app)?,
+        );
+    }
+    if !profiles.is_empty() {
+        submenu = submenu.separator();
+    }
+    for action in [TrayAction::Login, TrayAction::Refresh, TrayAction::Logout] {
+        submenu = submenu.text(menu_id(action, &login_type, name), action.label());
+    }
+    submenu.build()
+}
+
+fn build_menu(app: &AppHandle, config: &ButlerSsoConfig) -> tauri::Result<Menu<tauri::Wry>> {
+    let mut menu = MenuBuilder::new(app);
+
+    let mut sessions = config.sessions.iter().collect::<Vec<_>>();
+    sessions.sort_by(|a, b| a.session_name.cmp(&b.session_name));
+    for sess in sessions {
+        let mut profiles = config
+            .sso_profiles
+            .iter()
+            .filter(|prof| prof.session_name == sess.session_name)
+            .map(|prof| {
+                (
+                    prof.profile_name.clone(),
+                    describe_expiry(prof.fresh, prof.profile_expiration),
+                )
+            })
+            .collect::<Vec<_>>();
+        profiles.sort();
+        menu = menu.item(&target_submenu(
+            app,
+            LoginType::SsoSession,
+            &sess.session_name,
+            &describe_expiry(sess.fresh, sess.session_expiration),
+            &profiles,
+        )?);
+    }
+
+    let mut legacy_profiles = config.legacy_profiles.iter().collect::<Vec<_>>();
+    legacy_profiles.sort_by(|a, b| a.profile_name.cmp(&b.profile_name));
+    for prof in legacy_profiles {
+        menu = menu.item(&target_submenu(
+            app,
+            LoginType::LegacyProfile,
+            &prof.profile_name,
+            &describe_expiry(prof.fresh, prof.profile_expiration),
+            &[],
+        )?);
+    }
+
+    if config.sessions.is_empty() && config.legacy_profiles.is_empty() {
+        menu = menu.item(
+            &MenuItemBuilder::new("No SSO sessions configured")
+                .enabled(false)
+                .build(app)?,
+        );
+    }
+    menu.separator()
+        .text(SHOW_ID, "Show Awth Butler")
+        .text(QUIT_ID, "Quit")
+        .build()
+}
+
+fn tooltip(config: &ButlerSsoConfig) -> String {
+    let fresh = config.sessions.iter().filter(|s| s.fresh).count()
+        + config.legacy_profiles.iter().filter(|p| p.fresh).count();
+    let total = config.sessions.len() + config.legacy_profiles.len();
+    format!("Awth Butler: {} of {} logins fresh", fresh, total)
+}
+
+/// Rebuilds the tray menu from the same overview `fetch_butler_config` serves.
+async fn refresh_tray(app: &AppHandle) -> Result<(), anyhow::Error> {
+    let config = {
+        let state = app.state::<Mutex<ButlerState>>();
+        let state = state.lock().await;
+        build_butler_config(&state.aws_profiles, &state.settings)?
+    };
+    let tray = app
+        .tray_by_id(TRAY_ID)
+        .ok_or_else(|| trace_err_ret("tray icon not found"))?;
+    tray.set_menu(Some(build_menu(app, &config)?))?;
+    tray.set_tooltip(Some(tooltip(&config)))?;
+    Ok(())
+}
+
+/// Updates the tray in the background after the state behind it changed.
+pub(crate) fn update_tray(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = refresh_tray(&app).await {
+            tracing::error!("unable to update the tray: {:?}", e);
+        }
+    });
+}
+
+fn show_main_window(app: &AppHandle) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    if let Err(e) = window
+        .show()
+        .and_then(|_| window.unminimize())
+        .and_then(|_| window.set_focus())
+    {
+        tracing::warn!("unable to show the main window: {:?}", e);
+    }
+}
+
+fn show_error(app: &AppHandle, msg: String) {
+    app.dialog()
+        .message(msg)
+        .kind(MessageDialogKind::Error)
+        .title("Awth Butler")
+        .show(|_| {});
+}
+
+async fn run_action(
+    app: &AppHandle,
+    action: TrayAction,
+    login_type: LoginType,
+    name: &str,
+) -> Result<(), anyhow::Error> {
+    let state = app.state::<Mutex<ButlerState>>();
+    match action {
+        TrayAction::Login => {
+            let target = LoginTarget {
+                login_type,
+                name: name.to_string(),
+                selection: ProfileSelection::All,
+            };
+            let report = run_login(app.clone(), state, target)
+                .await
+                .map_err(|e| trace_err_ret(&e))?;
+            let failed = report
+                .failures()
+                .map(|res| res.profile_name.as_str())
+                .collect::<Vec<_>>();
+            if !failed.is_empty() {
+                return Err(trace_err_ret(&format!(
+                    "Logged into {}, but these profiles failed: {}",
+                    name,
+                    failed.join(", ")
+                )));
+            }
+        }
+        TrayAction::Refresh => {
+            let target = LoginTarget {
+                login_type,
+                name: name.to_string(),
+                selection: ProfileSelection::All,
+            };
+            let refresh = {
+                let mut state = state.lock().await;
+                if state.logins.find(&target).is_some() {
+                    return Err(trace_err_ret(&format!(
+                        "{} is already logging in, its credentials are refreshed once that's done!",
+                        name
+                    )));
+                }
+                let profile_set = state.aws_profiles.clone();
+                let settings = state.settings.clone();
+                state.logins.start(target.clone(), |login_id, cancelled| {
+                    let prompt = SilentPrompt {
+                        app: app.clone(),
+                        login_id,
+                        target: target.clone(),
+                        cancelled,
+                    };
+                    spawn_login(app.clone(), profile_set, settings, target, login_id, prompt)
+                })
+            };
+            let report = refresh.await.map_err(|e| trace_err_ret(&e))?;
+            if let Some(res) = report.failures().next() {
+                return Err(trace_err_ret(&format!(
+                    "Unable to refresh {}: {:?}",
+                    res.profile_name, res.outcome
+                )));
+            }
+        }
+        TrayAction::Logout => {
+            let profile_set = state.lock().await.aws_profiles.clone();
+            match login_type {
+                LoginType::SsoSession => sso_session_logout(&profile_set, name).await?,
+                LoginType::LegacyProfile => profile_logout(&profile_set, name).await?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs an action in the background, with no window to report to errors
+/// show in a dialog.
+fn spawn_action(app: &AppHandle, action: TrayAction, login_type: LoginType, name: String) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = run_action(&app, action, login_type, &name).await {
+            show_error(&app, e.to_string());
+        }
+        update_tray(&app);
+    });
+}
+
+/// Logs into a session or legacy profile from outside the main window.
+pub(crate) fn start_login(app: &AppHandle, login_type: LoginType, name: String) {
+    spawn_action(app, TrayAction::Login, login_type, name);
+}
+
+fn on_menu_event(app: &AppHandle, event: MenuEvent) {
+    match event.id().as_ref() {
+        SHOW_ID => show_main_window(app),
+        QUIT_ID => app.exit(0),
+        id => {
+            if let Some((action, login_type, name)) = parse_menu_id(id) {
+                spawn_action(app, action, login_type, name.to_string());
+            }
+        }
+    }
+}
+
+/// Builds the tray icon and keeps its menu up to date.
+pub(crate) fn create_tray(app: &AppHandle) -> Result<(), anyhow::Error> {
+    let icon = app
+        .default_window_icon()
+        .ok_or_else(|| trace_err_ret("app has no icon"))?
+        .clone();
+    TrayIconBuilder::with_id(TRAY_ID)
+        .icon(icon)
+        .tooltip("Awth Butler")
+        .menu(&MenuBuilder::new(app).text(QUIT_ID, "Quit").build()?)
+        .show_menu_on_left_click(false)
+        .on_menu_event(on_menu_event)
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                show_main_window(tray.app_handle());
+            }
+        })
+        .build(app)?;
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut ticker = tokio::time::interval(UPDATE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = refresh_tray(&app).await {
+                tracing::error!("unable to update the tray: {:?}", e);
+            }
+        }
+    });
+    Ok(())
+}
@@ -7,6 +7,32 @@ pub(crate) fn fetch_profiles_new() -> Result<AwsConfigSections, anyhow::Error> {
     AwsConfigSections::parse()
 }
 
+/// Matches `*` against any run of characters and `?` against exactly one,
+/// everything else has to match as is.
+pub(crate) fn matches_wildcard(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // where the last `*` was seen and how much text it swallowed so far
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
 pub(crate) fn parse_aws_date_robust(date_str: &str) -> Result<DateTime<Utc>, anyhow::Error> {
     let formats = [
         "%Y-%m-%dT%H:%M:%S%:z", // Standard RFC3339
@@ -29,3 +55,56 @@ pub(crate) fn parse_aws_date_robust(date_str: &str) -> Result<DateTime<Utc>, any
     tracing::error!("{}", err);
     Err(err)
 }
+
+/// How long credentials or a token have left, or when they ran out.
+pub(crate) fn describe_expiry(fresh: bool, expiration: Option<DateTime<Utc>>) -> String {
+    match expiration {
+        Some(exp) if fresh => {
+            let remaining = exp - Utc::now();
+            format!(
+                "fresh, {}h {:02}m left",
+                remaining.num_hours(),
+                remaining.num_minutes() % 60
+            )
+        }
+        Some(exp) => format!("stale, expired {}", exp.format("%Y-%m-%d %H:%M:%S UTC")),
+        None => "stale".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_match_like_a_shell() {
+        for (pattern, text, matches) in [
+            ("prod-*", "prod-admin", true),
+            ("prod-*", "prod-", true),
+            ("prod-*", "dev-admin", false),
+            ("*-admin", "prod-admin", true),
+            ("*-admin", "prod-admin-2", false),
+            ("*a*b*", "xxaxxbxx", true),
+            ("*a*b", "xxaxxbxxb", true),
+            ("*a*b", "xxbxxa", false),
+            ("dev-?", "dev-1", true),
+            ("dev-?", "dev-", false),
+            ("dev-?", "dev-12", false),
+            ("a*", "a*b", true),
+            ("*", "", true),
+            ("*", "anything", true),
+            ("", "", true),
+            ("", "dev", false),
+            ("dev", "dev", true),
+            ("dev", "Dev", false),
+        ] {
+            assert_eq!(
+                matches_wildcard(pattern, text),
+                matches,
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+}
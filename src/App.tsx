@@ -1,4 +1,4 @@
-import { createSignal } from "solid-js";
+import { createSignal, Show } from "solid-js";
 import butlogo from "./../app-icon.png";
 import ThemeSelect from "./components/ThemeSelect";
 import { invoke } from "@tauri-apps/api/core";
@@ -7,20 +7,38 @@ import { LoginType } from "./types/LoginType";
 import "./App.css";
 import {
   ButerSsoProfile,
+  ButlerChainedProfile,
   ButlerSsoConfig,
   ButlerSsoLegacyProfile,
   ButlerSsoSession,
 } from "./types/ButlerSsoConfig";
 import {
+  ChainedProfileTable,
   LegacyProfileTable,
   SsoProfileTable,
   SsoSessionTable,
 } from "./components/ConfigTables";
+import { DiscoveryTable } from "./components/DiscoveryTable";
+import { GenerateProfiles } from "./components/GenerateProfiles";
+import { SessionLogin } from "./components/SessionLogin";
+import { ConfigEditor } from "./components/ConfigEditor";
+import { SettingsEditor } from "./components/SettingsEditor";
+import { SectionType } from "./types/SectionType";
+import { DiagnosticsTable } from "./components/DiagnosticsTable";
+import { Finding } from "./types/Finding";
+import { LoginReportTable } from "./components/LoginReportTable";
+import { LoginReport } from "./types/LoginReport";
+import {
+  applyProgress,
+  LoginProgressList,
+} from "./components/LoginProgressList";
+import { LoginProgress, LoginStatus } from "./types/LoginProgress";
+import { SessionDiscovery } from "./types/SessionDiscovery";
 import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
 
 type SelectedRowData = {
   name: string;
-  table: "sessions" | "ssos" | "legacies";
+  table: "sessions" | "ssos" | "legacies" | "chained";
 };
 
 function App() {
@@ -32,10 +50,28 @@ function App() {
   const [selectedRow, setSelectedRow] = createSignal<SelectedRowData | null>(
     null,
   );
+  const [discovery, setDiscovery] = createSignal<SessionDiscovery | null>(
+    null,
+  );
+  const [loginReport, setLoginReport] = createSignal<LoginReport | null>(
+    null,
+  );
+  const [findings, setFindings] = createSignal<Finding[]>([]);
+  const [progress, setProgress] = createSignal<Record<number, LoginStatus>>(
+    {},
+  );
+  const [loggingIn, setLoggingIn] = createSignal<
+    { loginType: LoginType; name: string } | null
+  >(null);
+  const [showSettings, setShowSettings] = createSignal(false);
+  const [editing, setEditing] = createSignal<
+    { sectionType: SectionType; name: string | null } | null
+  >(null);
 
   async function fetch_config() {
     let butConf: ButlerSsoConfig = await invoke("fetch_butler_config", {});
     setButlerConfig(butConf);
+    setFindings(await invoke("diagnose_config", {}));
   }
 
   async function refresh_profiles() {
@@ -50,19 +86,44 @@ function App() {
   }
 
   async function authenticate_aws() {
-    await invoke("authenticate_aws", {
+    const target = { loginType: loginType()!, name: name()! };
+    setLoggingIn(target);
+    try {
+      let report: LoginReport = await invoke("authenticate_aws", target);
+      setLoginReport(report);
+    } finally {
+      setLoggingIn(null);
+    }
+  }
+
+  async function cancel_login() {
+    await invoke("cancel_login", { ...loggingIn() });
+  }
+
+  async function logout() {
+    await invoke("logout", {
       loginType: loginType(),
       name: name(),
     });
   }
 
+  async function discover_accounts(refresh: boolean) {
+    let disc: SessionDiscovery = await invoke("discover_accounts", {
+      sessionName: name(),
+      refresh: refresh,
+    });
+    setDiscovery(disc);
+  }
+
   const resetSelection = () => {
+    setDiscovery(null);
     setSelectedRow(null);
     setName(null);
     setLoginType(null);
   };
 
   const handleSessionSelection = (item: ButlerSsoSession) => {
+    setDiscovery(null);
     setSelectedRow({ name: item.session_name, table: "sessions" });
     setName(item.session_name);
     setLoginType("SsoSession");
@@ -70,21 +131,31 @@ function App() {
 
   // Function to handle row selection in table 2
   const handleLegacyProfileSelection = (item: ButlerSsoLegacyProfile) => {
+    setDiscovery(null);
     setSelectedRow({ name: item.profile_name, table: "legacies" });
     setName(item.profile_name);
     setLoginType("LegacyProfile");
   };
 
   const handleSsoProfileSelection = (item: ButerSsoProfile) => {
+    setDiscovery(null);
     setSelectedRow({ name: item.profile_name, table: "ssos" });
     setName(item.profile_name);
     setLoginType("LegacyProfile");
   };
 
+  // chained profiles log in through the SSO profile their chain starts at
+  const handleChainedProfileSelection = (item: ButlerChainedProfile) => {
+    setDiscovery(null);
+    setSelectedRow({ name: item.profile_name, table: "chained" });
+    setName(item.profile_name);
+    setLoginType("LegacyProfile");
+  };
+
   // Check if a row is selected
   const isSelected = (
     id: string,
-    table: "sessions" | "legacies" | "ssos",
+    table: "sessions" | "legacies" | "ssos" | "chained",
   ): boolean => {
     const current = selectedRow();
     return current !== null && current.name === id && current.table === table;
@@ -115,6 +186,21 @@ function App() {
       });
   });
 
+  // finished logins drop out, their outcome shows in the login report
+  appWebview.listen<LoginProgress>("login-progress", (event) => {
+    const update = event.payload;
+    setProgress((current) => {
+      const next = { ...current };
+      const step = update.step;
+      if (typeof step === "object" && ("Done" in step || "Failed" in step)) {
+        delete next[update.login_id];
+      } else {
+        next[update.login_id] = applyProgress(current[update.login_id], update);
+      }
+      return next;
+    });
+  });
+
   const authBtnText = (): string => {
     if (loginType()) {
       if (loginType() === "SsoSession") {
@@ -140,7 +226,14 @@ function App() {
             Awth Butler
           </h1>
 
-          {ThemeSelect("float-right ml-auto pr-2 align-middle")}
+          <button
+            class="btn btn-ghost ml-auto mr-2"
+            onClick={() => setShowSettings(true)}
+          >
+            Settings
+          </button>
+
+          {ThemeSelect("float-right pr-2 align-middle")}
         </div>
 
         <div class="w-3xl mx-auto">
@@ -154,9 +247,85 @@ function App() {
             </button> */
             }
 
+            <div class="dropdown mr-auto">
+              <div tabindex="0" role="button" class="btn btn-outline">
+                New
+              </div>
+              <ul
+                tabindex="0"
+                class="dropdown-content menu bg-base-200 rounded-box z-1 w-40 p-2 shadow"
+              >
+                <li>
+                  <a
+                    onClick={() =>
+                      setEditing({ sectionType: "SsoSession", name: null })}
+                  >
+                    Session
+                  </a>
+                </li>
+                <li>
+                  <a
+                    onClick={() =>
+                      setEditing({ sectionType: "Profile", name: null })}
+                  >
+                    Profile
+                  </a>
+                </li>
+              </ul>
+            </div>
+
+            <button
+              class="btn btn-outline disabled:opacity-40 mr-4"
+              onClick={() =>
+                setEditing({
+                  sectionType: loginType() === "SsoSession"
+                    ? "SsoSession"
+                    : "Profile",
+                  name: name(),
+                })}
+              disabled={!selectedRow()}
+            >
+              Edit
+            </button>
+
+            <button
+              class="btn btn-outline disabled:opacity-40 mr-4"
+              onClick={() =>
+                discover_accounts(false).catch((error) =>
+                  message("Error discovering accounts: " + error)
+                )}
+              disabled={loginType() !== "SsoSession"}
+            >
+              Discover Accounts
+            </button>
+
+            <button
+              class="btn btn-outline disabled:opacity-40 mr-4"
+              onClick={() =>
+                logout().catch((error) => message("Error logging out: " + error))}
+              disabled={!selectedRow()}
+            >
+              Logout
+            </button>
+
+            <Show when={loggingIn()}>
+              <button
+                class="btn btn-outline btn-error mr-4"
+                onClick={() =>
+                  cancel_login().catch((error) =>
+                    message("Error cancelling login: " + error)
+                  )}
+              >
+                Cancel Login
+              </button>
+            </Show>
+
             <button
               class="btn bg-gradient-to-br from-primary to-secondary text-primary-content disabled:opacity-40 min-w-40 mr-4"
-              onClick={authenticate_aws}
+              onClick={() =>
+                authenticate_aws().catch((error) =>
+                  message("Error authenticating: " + error)
+                )}
               disabled={!selectedRow()}
             >
               {authBtnText()}
@@ -164,6 +333,46 @@ function App() {
           </div>
 
           <div class="flex flex-col p-4 gap-6">
+            {Object.keys(progress()).length > 0 &&
+              LoginProgressList(Object.values(progress()), (login) =>
+                invoke("cancel_login", {
+                  loginType: login.login_type,
+                  name: login.name,
+                }).catch((error) =>
+                  message("Error cancelling login: " + error)
+                ))}
+
+            <Show when={loginReport()} keyed>
+              {(report) =>
+                LoginReportTable(report, () => setLoginReport(null))}
+            </Show>
+
+            <Show when={showSettings()}>
+              <SettingsEditor
+                onDone={() => {
+                  setShowSettings(false);
+                  fetch_config().catch((error) =>
+                    message("Error fetching config: " + error)
+                  );
+                }}
+              />
+            </Show>
+
+            <Show when={editing()} keyed>
+              {(edit) => (
+                <ConfigEditor
+                  sectionType={edit.sectionType}
+                  name={edit.name}
+                  onDone={() => {
+                    setEditing(null);
+                    refresh_profiles().catch((error) =>
+                      message("Error fetching config: " + error)
+                    );
+                  }}
+                />
+              )}
+            </Show>
+
             {SsoSessionTable(
               butlerConfig()?.sessions,
               isSelected,
@@ -181,6 +390,38 @@ function App() {
               isSelected,
               handleLegacyProfileSelection,
             )}
+
+            {ChainedProfileTable(
+              butlerConfig()?.chained_profiles,
+              isSelected,
+              handleChainedProfileSelection,
+            )}
+
+            {findings().length > 0 && DiagnosticsTable(findings())}
+
+            {discovery() &&
+              DiscoveryTable(discovery(), () =>
+                discover_accounts(true).catch((error) =>
+                  message("Error discovering accounts: " + error)
+                ))}
+
+            <Show when={loginType() === "SsoSession" && name()} keyed>
+              {(sessionName) => (
+                <SessionLogin
+                  sessionName={sessionName}
+                  profileNames={butlerConfig()?.sessions.find((s) =>
+                    s.session_name === sessionName
+                  )?.profile_names ?? []}
+                  onReport={setLoginReport}
+                />
+              )}
+            </Show>
+
+            <Show when={loginType() === "SsoSession" && name()}>
+              {(sessionName) => (
+                <GenerateProfiles sessionName={sessionName()} />
+              )}
+            </Show>
           </div>
         </div>
       </div>
@@ -0,0 +1,129 @@
+import { createEffect, createSignal, Show } from "solid-js";
+import { invoke } from "@tauri-apps/api/core";
+import { ask, message } from "@tauri-apps/plugin-dialog";
+import { SectionProperties, SectionType } from "../types/SectionType";
+
+function toText(properties: SectionProperties): string {
+  return Object.entries(properties)
+    .map(([key, value]) => key + " = " + value)
+    .join("\n");
+}
+
+function fromText(text: string): SectionProperties {
+  const properties: SectionProperties = {};
+  for (const line of text.split("\n")) {
+    const idx = line.indexOf("=");
+    if (line.trim() === "" || idx === -1) {
+      continue;
+    }
+    properties[line.slice(0, idx).trim()] = line.slice(idx + 1).trim();
+  }
+  return properties;
+}
+
+// Edits an existing section when a name is given, otherwise creates one.
+export function ConfigEditor(props: {
+  sectionType: SectionType;
+  name: string | null;
+  onDone: () => void;
+}) {
+  const [name, setName] = createSignal(props.name ?? "");
+  const [text, setText] = createSignal("");
+
+  createEffect(() => {
+    if (props.name === null) {
+      setText(
+        props.sectionType === "SsoSession"
+          ? "sso_start_url = \nsso_region = \nsso_registration_scopes = sso:account:access"
+          : "sso_session = \nsso_account_id = \nsso_role_name = \nregion = ",
+      );
+      return;
+    }
+    invoke<SectionProperties>("get_config_section", {
+      sectionType: props.sectionType,
+      name: props.name,
+    })
+      .then((properties) => setText(toText(properties)))
+      .catch((error) => message("Error reading section: " + error));
+  });
+
+  async function save() {
+    if (props.name === null) {
+      await invoke("create_config_section", {
+        sectionType: props.sectionType,
+        name: name(),
+        properties: fromText(text()),
+      });
+    } else {
+      if (name() !== props.name) {
+        await invoke("rename_config_section", {
+          sectionType: props.sectionType,
+          oldName: props.name,
+          newName: name(),
+        });
+      }
+      await invoke("update_config_section", {
+        sectionType: props.sectionType,
+        name: name(),
+        properties: fromText(text()),
+      });
+    }
+    props.onDone();
+  }
+
+  async function remove() {
+    if (
+      !(await ask("Delete " + props.name + " from your AWS config?", {
+        kind: "warning",
+      }))
+    ) {
+      return;
+    }
+    await invoke("delete_config_section", {
+      sectionType: props.sectionType,
+      name: props.name,
+    });
+    props.onDone();
+  }
+
+  return (
+    <div class="w-full">
+      <h3 class="font-bold mb-2">
+        {props.name === null ? "New " : "Edit "}
+        {props.sectionType === "SsoSession" ? "Session" : "Profile"}
+      </h3>
+      <input
+        class="input input-bordered w-full mb-2"
+        placeholder="name"
+        value={name()}
+        onInput={(e) => setName(e.currentTarget.value)}
+      />
+      <textarea
+        class="textarea textarea-bordered w-full font-mono h-40"
+        value={text()}
+        onInput={(e) => setText(e.currentTarget.value)}
+      />
+      <div class="flex justify-end gap-2 pt-2">
+        <Show when={props.name !== null}>
+          <button
+            class="btn btn-error btn-outline mr-auto"
+            onClick={() =>
+              remove().catch((error) =>
+                message("Error deleting section: " + error)
+              )}
+          >
+            Delete
+          </button>
+        </Show>
+        <button class="btn btn-outline" onClick={props.onDone}>Cancel</button>
+        <button
+          class="btn bg-gradient-to-br from-primary to-secondary text-primary-content"
+          onClick={() =>
+            save().catch((error) => message("Error saving section: " + error))}
+        >
+          Save
+        </button>
+      </div>
+    </div>
+  );
+}
@@ -1,6 +1,7 @@
 import { For } from "solid-js";
 import {
   ButerSsoProfile,
+  ButlerChainedProfile,
   ButlerSsoLegacyProfile,
   ButlerSsoSession,
 } from "../types/ButlerSsoConfig";
@@ -157,3 +158,53 @@ export function SsoProfileTable(
     </div>
   );
 }
+
+export function ChainedProfileTable(
+  profiles: ButlerChainedProfile[] | undefined,
+  isSelectedFn: (
+    name: string,
+    table: "sessions" | "legacies" | "ssos" | "chained",
+  ) => boolean,
+  onClickFn: (prof: ButlerChainedProfile) => void,
+) {
+  return (
+    <div class="w-full overflow-x-auto">
+      <h3 class="font-bold mb-2">Chained Profiles</h3>
+      <div class="overflow-x-auto rounded-box border border-base-content/10">
+        <table class="table w-full">
+          <thead class="bg-base-200">
+            <tr>
+              <th>Name</th>
+              <th>Source Profile</th>
+              <th>Status</th>
+              <th>Expiration</th>
+            </tr>
+          </thead>
+          <tbody>
+            <For
+              each={profiles?.sort((a, b) =>
+                a.profile_name.localeCompare(b.profile_name)
+              )}
+            >
+              {(prof) => (
+                <tr
+                  class={`hover:bg-base-300 hover:text-base-content cursor-pointer ${
+                    isSelectedFn(prof.profile_name, "chained")
+                      ? "bg-secondary text-secondary-content font-semibold"
+                      : ""
+                  }`}
+                  onClick={() => onClickFn(prof)}
+                >
+                  <td>{prof.profile_name}</td>
+                  <td>{prof.source_profile}</td>
+                  <td>{prof.fresh ? FreshBadge() : StaleBadge()}</td>
+                  <td>{displayDate(prof.profile_expiration)}</td>
+                </tr>
+              )}
+            </For>
+          </tbody>
+        </table>
+      </div>
+    </div>
+  );
+}
@@ -0,0 +1,57 @@
+import { For } from "solid-js";
+import { Finding, Severity } from "../types/Finding";
+
+function severityBadge(severity: Severity) {
+  const style = severity === "Error"
+    ? "badge-error"
+    : severity === "Warning"
+    ? "badge-warning"
+    : "badge-info";
+  return <div class={`badge ${style}`}>{severity}</div>;
+}
+
+function describeLocation(finding: Finding): string {
+  const loc = finding.location;
+  const section = loc.file === "Credentials"
+    ? loc.name
+    : (loc.section_type === "SsoSession" ? "sso-session " : "profile ") +
+      loc.name;
+  return (
+    loc.file.toLowerCase() + " [" + section + "]" +
+    (loc.key ? " " + loc.key : "")
+  );
+}
+
+export function DiagnosticsTable(findings: Finding[] | undefined) {
+  return (
+    <div class="w-full overflow-x-auto">
+      <h3 class="font-bold mb-2">Config Diagnostics</h3>
+      <div class="overflow-x-auto rounded-box border border-base-content/10">
+        <table class="table w-full">
+          <thead class="bg-base-200">
+            <tr>
+              <th>Severity</th>
+              <th>Problem</th>
+              <th>Location</th>
+              <th>Fix</th>
+            </tr>
+          </thead>
+          <tbody>
+            <For each={findings}>
+              {(finding) => (
+                <tr>
+                  <td>{severityBadge(finding.severity)}</td>
+                  <td>{finding.message}</td>
+                  <td class="font-mono text-xs">
+                    {describeLocation(finding)}
+                  </td>
+                  <td>{finding.hint}</td>
+                </tr>
+              )}
+            </For>
+          </tbody>
+        </table>
+      </div>
+    </div>
+  );
+}
@@ -0,0 +1,54 @@
+import { For } from "solid-js";
+import { SessionDiscovery } from "../types/SessionDiscovery";
+import displayDate from "../utils/DisplayDate";
+
+export function DiscoveryTable(
+  discovery: SessionDiscovery | null,
+  onRefreshFn: () => void,
+) {
+  return (
+    <div class="w-full overflow-x-auto">
+      <div class="flex items-center mb-2">
+        <h3 class="font-bold">
+          Available Accounts: {discovery?.session_name}
+        </h3>
+        <span class="text-sm opacity-60 ml-auto mr-2">
+          {discovery ? displayDate(discovery.discovered_at) : ""}
+        </span>
+        <button class="btn btn-sm btn-outline" onClick={onRefreshFn}>
+          Refresh
+        </button>
+      </div>
+      <div class="overflow-x-auto rounded-box border border-base-content/10">
+        <table class="table w-full">
+          <thead class="bg-base-200">
+            <tr>
+              <th>Account</th>
+              <th>Name</th>
+              <th>Email</th>
+              <th>Roles</th>
+            </tr>
+          </thead>
+          <tbody>
+            <For each={discovery?.accounts}>
+              {(account) => (
+                <tr>
+                  <td>{account.account_id}</td>
+                  <td>{account.account_name ?? ""}</td>
+                  <td>{account.email_address ?? ""}</td>
+                  <td>
+                    <ul>
+                      <For each={account.role_names}>
+                        {(role) => <li>{role}</li>}
+                      </For>
+                    </ul>
+                  </td>
+                </tr>
+              )}
+            </For>
+          </tbody>
+        </table>
+      </div>
+    </div>
+  );
+}
@@ -0,0 +1,127 @@
+import { createSignal, For, Show } from "solid-js";
+import { invoke } from "@tauri-apps/api/core";
+import { message } from "@tauri-apps/plugin-dialog";
+import {
+  GenerateOptions,
+  PlannedAction,
+  PlannedProfile,
+} from "../types/GeneratedProfiles";
+
+function describeAction(action: PlannedAction): string {
+  if (action === "Create") {
+    return "Create";
+  } else if (action === "Unchanged") {
+    return "Unchanged";
+  } else if (action === "NameTaken") {
+    return "Skip, name taken";
+  }
+  return "Skip, same as " + action.AlreadyConfigured;
+}
+
+export function GenerateProfiles(props: { sessionName: string }) {
+  const [template, setTemplate] = createSignal("{account_name}-{role_name}");
+  const [region, setRegion] = createSignal("");
+  const [output, setOutput] = createSignal("");
+  const [planned, setPlanned] = createSignal<PlannedProfile[] | null>(null);
+
+  const options = (): GenerateOptions => ({
+    session_name: props.sessionName,
+    template: template(),
+    region: region() || null,
+    output: output() || null,
+  });
+
+  const toCreate = () =>
+    (planned() ?? [])
+      .filter((plan) => plan.action === "Create")
+      .map((plan) => plan.profile_name);
+
+  async function preview() {
+    let plan: PlannedProfile[] = await invoke("preview_generated_profiles", {
+      options: options(),
+    });
+    setPlanned(plan);
+  }
+
+  async function apply() {
+    let written: string[] = await invoke("generate_profiles", {
+      options: options(),
+      profileNames: toCreate(),
+    });
+    setPlanned(null);
+    await message("Created " + written.length + " profiles");
+  }
+
+  return (
+    <div class="w-full overflow-x-auto">
+      <h3 class="font-bold mb-2">Generate Profiles: {props.sessionName}</h3>
+      <div class="flex gap-2 mb-2">
+        <input
+          class="input input-bordered grow"
+          placeholder="{account_name}-{role_name}"
+          value={template()}
+          onInput={(e) => setTemplate(e.currentTarget.value)}
+        />
+        <input
+          class="input input-bordered w-32"
+          placeholder="region"
+          value={region()}
+          onInput={(e) => setRegion(e.currentTarget.value)}
+        />
+        <input
+          class="input input-bordered w-24"
+          placeholder="output"
+          value={output()}
+          onInput={(e) => setOutput(e.currentTarget.value)}
+        />
+        <button
+          class="btn btn-outline"
+          onClick={() =>
+            preview().catch((error) =>
+              message("Error previewing profiles: " + error)
+            )}
+        >
+          Preview
+        </button>
+      </div>
+      <Show when={planned()}>
+        <div class="overflow-x-auto rounded-box border border-base-content/10">
+          <table class="table w-full">
+            <thead class="bg-base-200">
+              <tr>
+                <th>Profile</th>
+                <th>Account</th>
+                <th>Role</th>
+                <th>Change</th>
+              </tr>
+            </thead>
+            <tbody>
+              <For each={planned()}>
+                {(plan) => (
+                  <tr class={plan.action === "Create" ? "" : "opacity-60"}>
+                    <td>{plan.profile_name}</td>
+                    <td>{plan.account_id}</td>
+                    <td>{plan.role_name}</td>
+                    <td>{describeAction(plan.action)}</td>
+                  </tr>
+                )}
+              </For>
+            </tbody>
+          </table>
+        </div>
+        <div class="flex justify-end pt-2">
+          <button
+            class="btn bg-gradient-to-br from-primary to-secondary text-primary-content disabled:opacity-40"
+            onClick={() =>
+              apply().catch((error) =>
+                message("Error writing profiles: " + error)
+              )}
+            disabled={toCreate().length === 0}
+          >
+            Create {toCreate().length} Profiles
+          </button>
+        </div>
+      </Show>
+    </div>
+  );
+}
@@ -0,0 +1,87 @@
+import { For } from "solid-js";
+import { LoginProgress, LoginStatus } from "../types/LoginProgress";
+
+// Folds a progress event into the status of its login.
+export function applyProgress(
+  status: LoginStatus | undefined,
+  progress: LoginProgress,
+): LoginStatus {
+  const next: LoginStatus = status
+    ? { ...status }
+    : {
+      login_type: progress.login_type,
+      name: progress.name,
+      message: "Starting",
+      user_code: null,
+      fetched: 0,
+      failed: 0,
+      written: 0,
+    };
+  const step = progress.step;
+  if (step === "TokenReused") {
+    next.message = "Using the cached token";
+  } else if (step === "ClientRegistered") {
+    next.message = "Client registered";
+  } else if (step === "WaitingForApproval") {
+    next.message = "Waiting for approval in the browser";
+  } else if (step === "TokenReceived") {
+    next.message = "Signed in, fetching credentials";
+  } else if ("AuthorizationStarted" in step) {
+    next.message = "Authorization started";
+    next.user_code = step.AuthorizationStarted.user_code;
+  } else if ("CredentialsFetched" in step) {
+    if (step.CredentialsFetched.success) {
+      next.fetched += 1;
+    } else {
+      next.failed += 1;
+    }
+    next.message = "Fetched " + step.CredentialsFetched.profile_name;
+  } else if ("CredentialsWritten" in step) {
+    next.written += 1;
+    next.message = "Wrote " + step.CredentialsWritten.profile_name;
+  } else if ("Done" in step) {
+    next.message = "Done";
+  } else if ("Failed" in step) {
+    next.message = "Failed: " + step.Failed.error;
+  }
+  return next;
+}
+
+// Logins in a browser have no window to close, so each one can be cancelled
+// from here.
+export function LoginProgressList(
+  logins: LoginStatus[],
+  onCancel: (login: LoginStatus) => void,
+) {
+  return (
+    <div class="w-full flex flex-col gap-2">
+      <For each={logins}>
+        {(login) => (
+          <div class="alert">
+            <span class="loading loading-spinner loading-sm"></span>
+            <div class="flex flex-col">
+              <span class="font-bold">
+                {login.login_type === "SsoSession" ? "Session " : "Profile "}
+                {login.name}
+              </span>
+              <span>{login.message}</span>
+            </div>
+            <div class="ml-auto text-sm text-right">
+              {login.user_code && <div>Code: {login.user_code}</div>}
+              <div>
+                {login.written} written, {login.fetched} fetched,{" "}
+                {login.failed} failed
+              </div>
+            </div>
+            <button
+              class="btn btn-sm btn-outline btn-error"
+              onClick={() => onCancel(login)}
+            >
+              Cancel
+            </button>
+          </div>
+        )}
+      </For>
+    </div>
+  );
+}
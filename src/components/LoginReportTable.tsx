@@ -0,0 +1,79 @@
+import { For } from "solid-js";
+import { LoginReport, ProfileOutcome } from "../types/LoginReport";
+import FreshBadge from "./FreshBadge";
+
+const outcomeLabels: Record<string, string> = {
+  AccessDenied: "Access Denied",
+  TokenRejected: "Login Expired",
+  Misconfigured: "Misconfigured",
+  Throttled: "Throttled",
+  SourceFailed: "Skipped",
+  Failed: "Failed",
+};
+
+function outcomeCells(outcome: ProfileOutcome) {
+  if (outcome === "Success") {
+    return (
+      <>
+        <td>{FreshBadge()}</td>
+        <td></td>
+      </>
+    );
+  }
+  const [kind, reason] = Object.entries(outcome)[0];
+  return (
+    <>
+      <td>
+        <div
+          class={`badge ${
+            kind === "Throttled" ? "badge-warning" : "badge-error"
+          }`}
+        >
+          {outcomeLabels[kind] ?? kind}
+        </div>
+      </td>
+      <td>{reason}</td>
+    </>
+  );
+}
+
+export function LoginReportTable(
+  report: LoginReport,
+  onCloseFn: () => void,
+) {
+  return (
+    <div class="w-full overflow-x-auto">
+      <div class="flex items-center mb-2">
+        <h3 class="font-bold">Last Login</h3>
+        <button class="btn btn-sm btn-ghost ml-auto" onClick={onCloseFn}>
+          Dismiss
+        </button>
+      </div>
+      <div class="overflow-x-auto rounded-box border border-base-content/10">
+        <table class="table w-full">
+          <thead class="bg-base-200">
+            <tr>
+              <th>Profile</th>
+              <th>Result</th>
+              <th>Reason</th>
+            </tr>
+          </thead>
+          <tbody>
+            <For
+              each={[...report.profiles].sort((a, b) =>
+                a.profile_name.localeCompare(b.profile_name)
+              )}
+            >
+              {(result) => (
+                <tr>
+                  <td>{result.profile_name}</td>
+                  {outcomeCells(result.outcome)}
+                </tr>
+              )}
+            </For>
+          </tbody>
+        </table>
+      </div>
+    </div>
+  );
+}
@@ -0,0 +1,106 @@
+import { createSignal, For, Match, Switch } from "solid-js";
+import { invoke } from "@tauri-apps/api/core";
+import { message } from "@tauri-apps/plugin-dialog";
+import { LoginReport } from "../types/LoginReport";
+import { ProfileSelection } from "../types/ProfileSelection";
+
+type SelectionMode = "Names" | "Tag" | "Filter";
+
+export function SessionLogin(props: {
+  sessionName: string;
+  profileNames: string[];
+  onReport: (report: LoginReport) => void;
+}) {
+  const [mode, setMode] = createSignal<SelectionMode>("Names");
+  const [checked, setChecked] = createSignal<string[]>([]);
+  const [pattern, setPattern] = createSignal("");
+
+  const toggle = (profileName: string) =>
+    setChecked((current) =>
+      current.includes(profileName)
+        ? current.filter((name) => name !== profileName)
+        : [...current, profileName]
+    );
+
+  const selection = (): ProfileSelection => {
+    if (mode() === "Tag") {
+      return { Tag: pattern().trim() };
+    } else if (mode() === "Filter") {
+      return { Filter: pattern().trim() };
+    }
+    return { Names: checked() };
+  };
+
+  const isEmpty = () =>
+    mode() === "Names" ? checked().length === 0 : pattern().trim() === "";
+
+  async function login() {
+    let report: LoginReport = await invoke("authenticate_session_profiles", {
+      sessionName: props.sessionName,
+      selection: selection(),
+    });
+    props.onReport(report);
+  }
+
+  return (
+    <div class="w-full">
+      <h3 class="font-bold mb-2">Log In Some Profiles: {props.sessionName}</h3>
+      <div class="flex gap-2 mb-2">
+        <select
+          class="select select-bordered w-40"
+          value={mode()}
+          onChange={(e) => setMode(e.currentTarget.value as SelectionMode)}
+        >
+          <option value="Names">Pick profiles</option>
+          <option value="Tag">By tag</option>
+          <option value="Filter">By name pattern</option>
+        </select>
+        <Switch>
+          <Match when={mode() === "Tag"}>
+            <input
+              class="input input-bordered grow"
+              placeholder="tag from butler_tags"
+              value={pattern()}
+              onInput={(e) => setPattern(e.currentTarget.value)}
+            />
+          </Match>
+          <Match when={mode() === "Filter"}>
+            <input
+              class="input input-bordered grow"
+              placeholder="prod-*"
+              value={pattern()}
+              onInput={(e) => setPattern(e.currentTarget.value)}
+            />
+          </Match>
+        </Switch>
+        <button
+          class="btn btn-outline disabled:opacity-40 ml-auto"
+          onClick={() =>
+            login().catch((error) => message("Error authenticating: " + error))}
+          disabled={isEmpty()}
+        >
+          Authenticate Selected
+        </button>
+      </div>
+      <Switch>
+        <Match when={mode() === "Names"}>
+          <div class="flex flex-wrap gap-x-6 gap-y-1">
+            <For each={props.profileNames}>
+              {(profileName) => (
+                <label class="label cursor-pointer gap-2">
+                  <input
+                    type="checkbox"
+                    class="checkbox checkbox-sm"
+                    checked={checked().includes(profileName)}
+                    onChange={() => toggle(profileName)}
+                  />
+                  <span>{profileName}</span>
+                </label>
+              )}
+            </For>
+          </div>
+        </Match>
+      </Switch>
+    </div>
+  );
+}
@@ -0,0 +1,180 @@
+import { createSignal, onMount, Show } from "solid-js";
+import { invoke } from "@tauri-apps/api/core";
+import { message } from "@tauri-apps/plugin-dialog";
+import { LoginFlow, SectionSettings, Settings } from "../types/Settings";
+
+function toNumber(text: string): number | null {
+  return text.trim() === "" ? null : Number(text);
+}
+
+// Edits the defaults and notifications in a form, the per-session and
+// per-profile overrides as JSON.
+export function SettingsEditor(props: { onDone: () => void }) {
+  const [settings, setSettings] = createSignal<Settings | null>(null);
+  const [overrides, setOverrides] = createSignal("");
+
+  onMount(() => {
+    invoke<Settings>("load_settings")
+      .then((loaded) => {
+        setSettings(loaded);
+        setOverrides(
+          JSON.stringify(
+            { sessions: loaded.sessions, profiles: loaded.profiles },
+            null,
+            2,
+          ),
+        );
+      })
+      .catch((error) => message("Error loading settings: " + error));
+  });
+
+  const setDefault = <K extends keyof SectionSettings>(
+    key: K,
+    value: SectionSettings[K],
+  ) =>
+    setSettings((current) =>
+      current && { ...current, defaults: { ...current.defaults, [key]: value } }
+    );
+
+  async function save() {
+    const parsed = JSON.parse(overrides());
+    await invoke("save_settings", {
+      settings: {
+        ...settings()!,
+        sessions: parsed.sessions ?? {},
+        profiles: parsed.profiles ?? {},
+      },
+    });
+    props.onDone();
+  }
+
+  return (
+    <Show when={settings()}>
+      {(current) => (
+        <div class="w-full">
+          <h3 class="font-bold mb-2">Settings</h3>
+          <p class="text-sm mb-2">
+            Keys set in your AWS config take precedence over these.
+          </p>
+          <div class="grid grid-cols-2 gap-2 items-center">
+            <span>Login flow</span>
+            <select
+              class="select select-bordered"
+              value={current().defaults.login_flow ?? ""}
+              onChange={(e) =>
+                setDefault(
+                  "login_flow",
+                  (e.currentTarget.value || null) as LoginFlow | null,
+                )}
+            >
+              <option value="">Device code</option>
+              <option value="authorization-code">Authorization code</option>
+            </select>
+
+            <span>Login browser</span>
+            <input
+              class="input input-bordered"
+              placeholder="embedded"
+              value={current().defaults.login_browser ?? ""}
+              onInput={(e) =>
+                setDefault("login_browser", e.currentTarget.value || null)}
+            />
+
+            <span>Login timeout (minutes)</span>
+            <input
+              type="number"
+              min="1"
+              class="input input-bordered"
+              placeholder="until the code expires"
+              value={current().defaults.login_timeout_minutes ?? ""}
+              onInput={(e) =>
+                setDefault(
+                  "login_timeout_minutes",
+                  toNumber(e.currentTarget.value),
+                )}
+            />
+
+            <span>Auto refresh every profile</span>
+            <input
+              type="checkbox"
+              class="checkbox"
+              checked={current().defaults.auto_refresh ?? false}
+              onChange={(e) =>
+                setDefault("auto_refresh", e.currentTarget.checked || null)}
+            />
+
+            <span>Refresh lead time (minutes)</span>
+            <input
+              type="number"
+              min="1"
+              class="input input-bordered"
+              placeholder="10"
+              value={current().defaults.refresh_lead_minutes ?? ""}
+              onInput={(e) =>
+                setDefault(
+                  "refresh_lead_minutes",
+                  toNumber(e.currentTarget.value),
+                )}
+            />
+
+            <span>Expiry notifications</span>
+            <input
+              type="checkbox"
+              class="checkbox"
+              checked={current().notifications.enabled}
+              onChange={(e) => {
+                const enabled = e.currentTarget.checked;
+                setSettings((s) =>
+                  s && {
+                    ...s,
+                    notifications: { ...s.notifications, enabled },
+                  }
+                );
+              }}
+            />
+
+            <span>Notify at (minutes before expiry)</span>
+            <input
+              class="input input-bordered"
+              placeholder="30, 5"
+              value={current().notifications.thresholds_minutes.join(", ")}
+              onChange={(e) => {
+                const thresholds_minutes = e.currentTarget.value
+                  .split(",")
+                  .filter((part) => part.trim() !== "")
+                  .map(Number);
+                setSettings((s) =>
+                  s && {
+                    ...s,
+                    notifications: { ...s.notifications, thresholds_minutes },
+                  }
+                );
+              }}
+            />
+          </div>
+
+          <h4 class="font-bold mt-4 mb-2">Session and profile overrides</h4>
+          <textarea
+            class="textarea textarea-bordered w-full font-mono h-40"
+            value={overrides()}
+            onInput={(e) => setOverrides(e.currentTarget.value)}
+          />
+          <div class="flex justify-end gap-2 pt-2">
+            <button class="btn btn-outline" onClick={props.onDone}>
+              Cancel
+            </button>
+            <button
+              class="btn bg-gradient-to-br from-primary to-secondary text-primary-content"
+              onClick={() =>
+                save().catch((error) =>
+                  message("Error saving settings: " + error)
+                )}
+            >
+              Save
+            </button>
+          </div>
+        </div>
+      )}
+    </Show>
+  );
+}
@@ -3,6 +3,7 @@ export type ButlerSsoSession = {
   session_expiration: string | null;
   fresh: boolean;
   profile_names: string[];
+  registration_expiration: string | null;
 };
 
 export type ButerSsoProfile = {
@@ -16,10 +17,20 @@ export type ButlerSsoLegacyProfile = {
   profile_name: string;
   profile_expiration: string | null;
   fresh: boolean;
+  registration_expiration: string | null;
+};
+
+export type ButlerChainedProfile = {
+  profile_name: string;
+  source_profile: string;
+  root_profile: string;
+  profile_expiration: string | null;
+  fresh: boolean;
 };
 
 export type ButlerSsoConfig = {
   sessions: ButlerSsoSession[];
   sso_profiles: ButerSsoProfile[];
   legacy_profiles: ButlerSsoLegacyProfile[];
+  chained_profiles: ButlerChainedProfile[];
 };
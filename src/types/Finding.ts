@@ -0,0 +1,15 @@
+import { SectionType } from "./SectionType";
+
+export type Severity = "Error" | "Warning" | "Info";
+
+export type Finding = {
+  severity: Severity;
+  location: {
+    file: "Config" | "Credentials";
+    section_type: SectionType;
+    name: string;
+    key: string | null;
+  };
+  message: string;
+  hint: string;
+};
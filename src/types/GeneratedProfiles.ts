@@ -0,0 +1,19 @@
+export type GenerateOptions = {
+  session_name: string;
+  template: string;
+  region: string | null;
+  output: string | null;
+};
+
+export type PlannedAction =
+  | "Create"
+  | "Unchanged"
+  | "NameTaken"
+  | { AlreadyConfigured: string };
+
+export type PlannedProfile = {
+  profile_name: string;
+  account_id: string;
+  role_name: string;
+  action: PlannedAction;
+};
@@ -0,0 +1,30 @@
+import { LoginType } from "./LoginType";
+
+export type LoginStep =
+  | "TokenReused"
+  | "ClientRegistered"
+  | { AuthorizationStarted: { user_code: string | null; verification_uri: string } }
+  | "WaitingForApproval"
+  | "TokenReceived"
+  | { CredentialsFetched: { profile_name: string; success: boolean } }
+  | { CredentialsWritten: { profile_name: string } }
+  | { Done: { succeeded: number; failed: number } }
+  | { Failed: { error: string } };
+
+export type LoginProgress = {
+  login_id: number;
+  login_type: LoginType;
+  name: string;
+  step: LoginStep;
+};
+
+// What the frontend keeps of a running login, folded from its events.
+export type LoginStatus = {
+  login_type: LoginType;
+  name: string;
+  message: string;
+  user_code: string | null;
+  fetched: number;
+  failed: number;
+  written: number;
+};
@@ -0,0 +1,17 @@
+export type ProfileOutcome =
+  | "Success"
+  | { AccessDenied: string }
+  | { TokenRejected: string }
+  | { Misconfigured: string }
+  | { Throttled: string }
+  | { SourceFailed: string }
+  | { Failed: string };
+
+export type ProfileResult = {
+  profile_name: string;
+  outcome: ProfileOutcome;
+};
+
+export type LoginReport = {
+  profiles: ProfileResult[];
+};
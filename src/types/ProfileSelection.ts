@@ -0,0 +1,5 @@
+export type ProfileSelection =
+  | "All"
+  | { Names: string[] }
+  | { Tag: string }
+  | { Filter: string };
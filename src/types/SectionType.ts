@@ -0,0 +1,3 @@
+export type SectionType = "SsoSession" | "Profile";
+
+export type SectionProperties = Record<string, string>;
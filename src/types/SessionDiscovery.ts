@@ -0,0 +1,12 @@
+export type DiscoveredAccount = {
+  account_id: string;
+  account_name: string | null;
+  email_address: string | null;
+  role_names: string[];
+};
+
+export type SessionDiscovery = {
+  session_name: string;
+  discovered_at: string;
+  accounts: DiscoveredAccount[];
+};
@@ -0,0 +1,23 @@
+export type LoginFlow = "device-code" | "authorization-code";
+
+// Unset fields fall through to the session, then to the defaults.
+export type SectionSettings = {
+  login_flow: LoginFlow | null;
+  login_browser: string | null;
+  login_timeout_minutes: number | null;
+  auto_refresh: boolean | null;
+  refresh_lead_minutes: number | null;
+};
+
+export type NotificationSettings = {
+  enabled: boolean;
+  thresholds_minutes: number[];
+};
+
+export type Settings = {
+  version: number;
+  defaults: SectionSettings;
+  sessions: Record<string, SectionSettings>;
+  profiles: Record<string, SectionSettings>;
+  notifications: NotificationSettings;
+};